@@ -0,0 +1,681 @@
+use anthropic::types::{CompleteRequest, CompleteRequestBuilder, CompleteResponse};
+use anthropic::{AI_PROMPT, DEFAULT_API_BASE, HUMAN_PROMPT};
+use clap::ValueEnum;
+use reqwest::header::RETRY_AFTER;
+use reqwest::StatusCode;
+use serde::Deserialize;
+use std::time::Duration;
+
+use crate::error::PrError;
+use crate::forge::ReviewComment;
+use crate::git::{AuthorCommits, CommitPatch, Revert};
+use crate::issue::IssueRef;
+use crate::retry::{retry_async, Backoff, Retry};
+
+pub const DEFAULT_MODEL: &str = "claude-3-haiku-20240307";
+/// Attempts per model before falling back to the next one.
+const MODEL_BACKOFF: Backoff = Backoff {
+    attempts: 3,
+    initial: Duration::from_secs(2),
+    max: Duration::from_secs(8),
+    jitter: true,
+};
+/// Longest `Retry-After` wait honored before trying again.
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(60);
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+pub const DEFAULT_MIN_CHARS: usize = 10;
+
+/// Where and how to reach the model.
+#[derive(Debug, Clone)]
+pub struct ModelConfig {
+    pub api_key: String,
+    pub api_base: String,
+    /// The preferred model followed by its fallbacks, in order.
+    pub models: Vec<String>,
+    /// Completions shorter than this, after trimming, are retried like an
+    /// unavailable model.
+    pub min_chars: usize,
+    /// Turn markers the prompt is wrapped in for the legacy completions API.
+    pub human_prompt: String,
+    pub ai_prompt: String,
+    pub stop_sequences: Vec<String>,
+}
+
+impl ModelConfig {
+    pub fn new(api_key: String, models: Vec<String>) -> Self {
+        ModelConfig {
+            api_key,
+            api_base: DEFAULT_API_BASE.to_string(),
+            models,
+            min_chars: DEFAULT_MIN_CHARS,
+            human_prompt: HUMAN_PROMPT.to_string(),
+            ai_prompt: AI_PROMPT.to_string(),
+            stop_sequences: vec![HUMAN_PROMPT.to_string()],
+        }
+    }
+}
+
+/// Checks a custom API base URL given on the command line, dropping any
+/// trailing slash since the client appends `/v1/complete` itself.
+pub fn parse_api_base(url: &str) -> Result<String, String> {
+    let parsed = reqwest::Url::parse(url).map_err(|e| format!("invalid URL: {}", e))?;
+    if !matches!(parsed.scheme(), "http" | "https") {
+        return Err(format!("expected an http or https URL, got `{}`", url));
+    }
+
+    Ok(url.trim_end_matches('/').to_string())
+}
+
+const DEFAULT_INSTRUCTIONS: &str =
+    "Please summarize the changes, their purpose, and any potential impact.";
+
+/// Who the description is written for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Audience {
+    /// Technical detail for the people reviewing the code
+    Reviewer,
+    /// A short, user-facing entry for a changelog
+    Changelog,
+    /// Release notes grouped into features, fixes and breaking changes
+    ReleaseNotes,
+}
+
+impl Audience {
+    fn instructions(self) -> &'static str {
+        match self {
+            Audience::Reviewer => {
+                "Write for the engineers reviewing this code. Be technical and specific: \
+                 include a short summary, then sections for the implementation details, \
+                 the risky or subtle parts that deserve careful review, and how the change \
+                 can be tested. Reference files and functions by name."
+            }
+            Audience::Changelog => {
+                "Write a changelog entry for end users. Keep it to a few sentences or \
+                 bullet points describing the user-visible impact in plain language. Leave \
+                 out implementation details, file names and internal refactors."
+            }
+            Audience::ReleaseNotes => {
+                "Write release notes. Use the sections \"Features\", \"Fixes\" and \
+                 \"Breaking Changes\", omitting any that would be empty, with one bullet \
+                 per user-facing change. Use a polished, announcement-ready tone and call \
+                 out anything users must do when upgrading."
+            }
+        }
+    }
+}
+
+/// Tweaks to the default prompt.
+#[derive(Debug, Default, Clone)]
+pub struct PromptOptions {
+    /// Extra instruction added to the end of the prompt.
+    pub append: Option<String>,
+    /// Replaces the default instructions with a preset for this audience.
+    pub audience: Option<Audience>,
+    /// Replaces the default instructions, e.g. with a shared team template. An
+    /// audience preset still takes precedence.
+    pub template: Option<String>,
+    /// Authors of the commits, listed as context.
+    pub authors: Vec<String>,
+    /// Only list this many of the most recent commits, noting how many were left out.
+    pub max_commits: Option<usize>,
+    /// Ask for a sentence or two instead of a full description.
+    pub brief: bool,
+    /// Ask for a "## Test Plan" section based on these changed test and CI
+    /// files, or one saying no tests changed when the list is empty.
+    pub test_plan: Option<Vec<String>>,
+    /// Ask for a "## Breaking Changes" section, pointing the model at these
+    /// removed public declarations.
+    pub breaking_changes: Option<Vec<String>>,
+    /// Recent pull request descriptions from the repository whose style the
+    /// new one should match.
+    pub style_examples: Vec<String>,
+    /// The diff is split per commit, see [`commit_sections`]; ask for one
+    /// section per commit.
+    pub by_commit: bool,
+    /// Leave the diff out of the prompt.
+    pub no_diff: bool,
+    /// Leave the commit messages out of the prompt.
+    pub no_commit_messages: bool,
+    /// Ask for a JSON object matching [`STRUCTURED_SHAPE`] instead of markdown.
+    pub structured: bool,
+    /// The commits the branch reverts; the description should say so.
+    pub reverts: Vec<Revert>,
+    /// List the commit messages under their authors instead of as one list.
+    pub by_author: Vec<AuthorCommits>,
+}
+
+/// The JSON object `--structured` asks the model for.
+pub const STRUCTURED_SHAPE: &str = "{\"summary\": string, \"changes\": [string], \
+                                    \"impact\": string, \"test_plan\": string or null}";
+/// How many times a malformed structured reply is sent back to be fixed.
+const STRUCTURED_REPAIR_ATTEMPTS: u32 = 2;
+
+/// A description in the fixed sections of `--structured`, rendered to
+/// markdown by the tool rather than the model.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct StructuredDescription {
+    pub summary: String,
+    #[serde(default)]
+    pub changes: Vec<String>,
+    #[serde(default)]
+    pub impact: String,
+    #[serde(default)]
+    pub test_plan: Option<String>,
+}
+
+impl StructuredDescription {
+    /// Parses the JSON object in a completion, ignoring any prose or code
+    /// fence the model wrapped it in.
+    pub fn parse(completion: &str) -> Result<Self, serde_json::Error> {
+        let start = completion.find('{').unwrap_or(0);
+        let end = completion.rfind('}').map_or(completion.len(), |i| i + 1);
+        serde_json::from_str(completion.get(start..end).unwrap_or(completion))
+    }
+
+    pub fn to_markdown(&self) -> String {
+        let mut body = format!("## Summary\n{}", self.summary.trim());
+        if !self.changes.is_empty() {
+            body.push_str("\n\n## Changes");
+            for change in &self.changes {
+                body.push_str(&format!("\n- {}", change.trim()));
+            }
+        }
+        if !self.impact.trim().is_empty() {
+            body.push_str(&format!("\n\n## Impact\n{}", self.impact.trim()));
+        }
+        if let Some(test_plan) = self.test_plan.as_deref().map(str::trim) {
+            if !test_plan.is_empty() {
+                body.push_str(&format!("\n\n## Test Plan\n{}", test_plan));
+            }
+        }
+        body
+    }
+
+    /// Fills the `{{summary}}`, `{{changes}}`, `{{impact}}` and `{{test_plan}}`
+    /// markers of a `--body-skeleton` file, leaving everything else as it is.
+    pub fn fill_skeleton(&self, skeleton: &str) -> String {
+        let changes: Vec<String> = self
+            .changes
+            .iter()
+            .map(|change| format!("- {}", change.trim()))
+            .collect();
+        let test_plan = match self.test_plan.as_deref().map(str::trim) {
+            Some(test_plan) if !test_plan.is_empty() => test_plan,
+            _ => "Nothing specific to test.",
+        };
+        skeleton
+            .replace("{{summary}}", self.summary.trim())
+            .replace("{{changes}}", &changes.join("\n"))
+            .replace("{{impact}}", self.impact.trim())
+            .replace("{{test_plan}}", test_plan)
+    }
+}
+
+/// Parses a `--structured` completion, sending it back to the model with the
+/// parse error when it isn't valid. The last parse error is returned when the
+/// repairs don't help either.
+pub async fn parse_structured(
+    completion: &str,
+    config: &ModelConfig,
+) -> Result<StructuredDescription, Box<dyn std::error::Error>> {
+    let mut completion = completion.to_string();
+    let mut attempt = 0;
+    loop {
+        let error = match StructuredDescription::parse(&completion) {
+            Ok(description) => return Ok(description),
+            Err(e) => e,
+        };
+        attempt += 1;
+        if attempt > STRUCTURED_REPAIR_ATTEMPTS {
+            return Err(format!("the reply is not valid JSON: {}", error).into());
+        }
+
+        eprintln!(
+            "Structured reply is not valid JSON ({}), asking for a fix ({}/{})",
+            error, attempt, STRUCTURED_REPAIR_ATTEMPTS
+        );
+        let prompt = format!(
+            "This reply should have been a single JSON object of the shape {}, but \
+             parsing it failed with: {}. Reply with the corrected JSON object only.\n\
+             Reply:\n{}",
+            STRUCTURED_SHAPE,
+            error,
+            completion.trim()
+        );
+        completion = generate_pr_description(&prompt, config).await?.0;
+    }
+}
+
+/// The map step for diffs too long for one prompt: each chunk is summarized on
+/// its own, and the joined summaries stand in for the diff.
+pub async fn summarize_diff(
+    chunks: &[String],
+    config: &ModelConfig,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let mut summaries = Vec::new();
+    for chunk in chunks {
+        let prompt = format!(
+            "Summarize what this part of a diff changes as a few bullet points, \
+             naming the files involved:\n{}",
+            chunk
+        );
+        let (summary, _) = generate_pr_description(&prompt, config).await?;
+        summaries.push(summary.trim().to_string());
+    }
+
+    Ok(format!(
+        "(too long to include, summarized in {} parts)\n{}",
+        chunks.len(),
+        summaries.join("\n")
+    ))
+}
+
+/// The prompt for the `commit-msg` subcommand.
+pub fn build_commit_message_prompt(diff: &str) -> String {
+    format!(
+        "Write a git commit message for this diff in the Conventional Commits format: \
+         a `type(scope): summary` subject line of at most 72 characters, then a blank \
+         line and a short body explaining what changed and why. Reply with the commit \
+         message only, without code fences.\n\
+         Diff: {}",
+        diff
+    )
+}
+
+/// The prompt for `--explain`: what the changes do, for a reviewer about to
+/// read them, rather than a pull request description.
+pub fn build_explain_prompt(diff: &str, commit_messages: &[String]) -> String {
+    format!(
+        "Explain these changes to a reviewer who is about to read the diff. Say what \
+         the code did before and what it does now, walking through the notable parts \
+         in order, and point out anything subtle or risky. This is not a pull request \
+         description: leave out a title and sections like a summary or test plan.\n\
+         Commit messages: {}\n\
+         Diff: {}",
+        commit_messages.join("\n"),
+        diff
+    )
+}
+
+/// The prompt for `--self-review`: pick out the hunks of the diff that most
+/// deserve a comment from the author, as a JSON array for inline comments.
+pub fn build_self_review_prompt(diff: &str) -> String {
+    format!(
+        "You are the author of this pull request, leaving self-review comments for \
+         reviewers. Pick the 2 to 4 most notable changes in the diff, such as subtle \
+         logic, trade-offs or things that look wrong but are deliberate, and explain \
+         each in a sentence or two. Reply with only a JSON array, without markdown or \
+         code fences, of objects of the shape {{\"path\": string, \"line\": number, \
+         \"body\": string}}, where \"line\" is a line number in the new version of \
+         the file that is added or shown as context in the diff.\n\
+         Diff: {}",
+        diff
+    )
+}
+
+/// Parses the JSON array of a `--self-review` reply, ignoring any text around it.
+pub fn parse_review_comments(completion: &str) -> Result<Vec<ReviewComment>, serde_json::Error> {
+    let start = completion.find('[').unwrap_or(0);
+    let end = completion.rfind(']').map_or(completion.len(), |i| i + 1);
+    serde_json::from_str(completion.get(start..end).unwrap_or(completion))
+}
+
+/// The prompt for the pull request title, written from the finished
+/// description so a cheaper model can do it.
+pub fn build_title_prompt(description: &str, commit_messages: &[String]) -> String {
+    format!(
+        "Write a title for this pull request: one line under 72 characters, in the \
+         imperative mood, without a trailing period. Reply with the title only.\n\
+         Commit messages: {}\n\
+         Description:\n{}",
+        commit_messages.join("\n"),
+        description
+    )
+}
+
+/// The first line of a title reply, without the quotes, heading marks or
+/// "Title:" label models like to add.
+pub fn clean_title(completion: &str) -> String {
+    let line = completion
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+        .unwrap_or_default();
+    let line = line.trim_start_matches('#').trim_start();
+    let line = line.strip_prefix("Title:").unwrap_or(line).trim();
+    line.trim_matches(|c| c == '"' || c == '`' || c == '\'')
+        .trim_end_matches('.')
+        .to_string()
+}
+
+/// The prompt for `--polish`: improve a description the user wrote, with the
+/// diff only as background.
+pub fn build_polish_prompt(draft: &str, diff: &str) -> String {
+    format!(
+        "Improve the clarity and structure of this pull request description while \
+         preserving its meaning. Don't add claims it doesn't make; use the diff only \
+         to get names and details right. Reply with the improved description only.\n\
+         Description:\n{}\n\
+         Diff: {}",
+        draft.trim(),
+        diff
+    )
+}
+
+/// The per-commit diffs joined for `--by-commit`, each under its subject.
+pub fn commit_sections(patches: &[CommitPatch]) -> String {
+    patches
+        .iter()
+        .map(|patch| format!("\nCommit: {}\n{}", patch.subject, patch.diff))
+        .collect()
+}
+
+/// Assembles the prompt sent to the model.
+pub fn build_prompt(
+    diff: &str,
+    commit_messages: &[String],
+    issues: &[IssueRef],
+    options: &PromptOptions,
+) -> String {
+    let issues = if issues.is_empty() {
+        "none".to_string()
+    } else {
+        issues
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    let commits = match options.max_commits {
+        Some(max) if commit_messages.len() > max => {
+            let mut shown = commit_messages[..max].join("\n");
+            shown.push_str(&format!(
+                "\n... and {} earlier commits",
+                commit_messages.len() - max
+            ));
+            shown
+        }
+        _ => commit_messages.join("\n"),
+    };
+    let mut prompt =
+        "Generate a pull request description based on the following information:\n".to_string();
+    if !options.no_diff {
+        prompt.push_str(&format!("Diff: {}\n", diff));
+    }
+    if !options.no_commit_messages && options.by_author.is_empty() {
+        prompt.push_str(&format!("Commit messages: {}\n", commits));
+    } else if !options.no_commit_messages {
+        prompt.push_str("Commit messages by author:\n");
+        for group in &options.by_author {
+            prompt.push_str(&format!("{}:\n", group.author));
+            for subject in &group.subjects {
+                prompt.push_str(&format!("- {}\n", subject));
+            }
+        }
+    }
+    prompt.push_str(&format!("Linked issues: {}\n", issues));
+    match options.authors.as_slice() {
+        [] => {}
+        [author] => prompt.push_str(&format!("Author: {}\n", author)),
+        authors => prompt.push_str(&format!(
+            "Authors: {} (a collaborative branch, credit them briefly)\n",
+            authors.join(", ")
+        )),
+    }
+    prompt.push_str(match (options.audience, &options.template) {
+        (Some(audience), _) => audience.instructions(),
+        (None, Some(template)) => template.trim(),
+        (None, None) => DEFAULT_INSTRUCTIONS,
+    });
+
+    match &options.test_plan {
+        Some(paths) if paths.is_empty() => prompt.push_str(
+            "\nEnd with a \"## Test Plan\" section containing only \"No tests added/changed.\"",
+        ),
+        Some(paths) => prompt.push_str(&format!(
+            "\nEnd with a \"## Test Plan\" section describing how the change is verified, \
+             based only on the changes to these test and CI files: {}",
+            paths.join(", ")
+        )),
+        None => {}
+    }
+
+    if let Some(removed) = &options.breaking_changes {
+        prompt.push_str(
+            "\nInclude a \"## Breaking Changes\" section listing public items that were \
+             removed or renamed and public signatures that changed, or saying \"None \
+             detected.\" when there are none.",
+        );
+        if !removed.is_empty() {
+            prompt.push_str(&format!(
+                " These removed public declarations are likely candidates:\n{}",
+                removed.join("\n")
+            ));
+        }
+    }
+
+    if !options.style_examples.is_empty() {
+        prompt.push_str(
+            "\nMatch the structure and tone of these recent pull request descriptions \
+             from the same repository:",
+        );
+        for example in &options.style_examples {
+            prompt.push_str(&format!("\n---\n{}", example));
+        }
+        prompt.push_str("\n---");
+    }
+
+    if !options.reverts.is_empty() {
+        let reverted: Vec<String> = options
+            .reverts
+            .iter()
+            .map(|revert| match &revert.sha {
+                Some(sha) => format!("\"{}\" ({})", revert.subject, &sha[..sha.len().min(12)]),
+                None => format!("\"{}\"", revert.subject),
+            })
+            .collect();
+        prompt.push_str(&format!(
+            "\nThis pull request reverts {}. Open by saying what is being reverted, \
+             including the original pull request when the subject names one like \
+             (#123), and why if the commit messages say. Describe the removed changes \
+             as being backed out, not as new work.",
+            reverted.join(", ")
+        ));
+    }
+
+    if options.by_commit {
+        prompt.push_str(
+            "\nThe diff is given commit by commit. After a short overall summary, write \
+             one \"### <commit subject>\" section per commit, in order, summarizing \
+             what that commit changes.",
+        );
+    }
+
+    if options.brief {
+        prompt
+            .push_str("\nKeep it brief: one or two concise sentences, with no headings or lists.");
+    }
+
+    if options.structured {
+        prompt.push_str(&format!(
+            "\nReply with only a JSON object of the shape {}, without markdown or code \
+             fences. List one change per item of \"changes\", and leave \"test_plan\" \
+             null when there is nothing specific to test.",
+            STRUCTURED_SHAPE
+        ));
+    }
+
+    if let Some(append) = &options.append {
+        prompt.push('\n');
+        prompt.push_str(append);
+    }
+
+    prompt
+}
+
+/// Generates the description with the first model in `config.models` that is
+/// available, returning the completion together with the model that produced it.
+pub async fn generate_pr_description(
+    prompt: &str,
+    config: &ModelConfig,
+) -> Result<(String, String), Box<dyn std::error::Error>> {
+    let http = reqwest::Client::new();
+
+    let mut last_error = None;
+    for model in &config.models {
+        let request = CompleteRequestBuilder::default()
+            .prompt(format!(
+                "{}{}\n{}",
+                config.human_prompt, prompt, config.ai_prompt
+            ))
+            .model(model.as_str())
+            .stream(false)
+            .max_tokens_to_sample(1_000_000_usize)
+            .stop_sequences(config.stop_sequences.clone())
+            .build()?;
+
+        let result = retry_async(
+            &MODEL_BACKOFF,
+            |e: &CompletionError, attempt| {
+                if !e.is_unavailable() {
+                    return Retry::No;
+                }
+                eprintln!(
+                    "{} is unavailable (attempt {}/{}): {}",
+                    model, attempt, MODEL_BACKOFF.attempts, e
+                );
+                match e.retry_after() {
+                    Some(delay) if attempt < MODEL_BACKOFF.attempts => {
+                        let delay = delay.min(MAX_RETRY_AFTER);
+                        eprintln!(
+                            "Waiting {}s before retrying, as the API asked",
+                            delay.as_secs()
+                        );
+                        Retry::After(delay)
+                    }
+                    _ => Retry::Yes,
+                }
+            },
+            |_| async {
+                let completion = complete(&http, config, &request).await?;
+                let chars = completion.trim().chars().count();
+                if chars < config.min_chars {
+                    return Err(CompletionError::TooShort {
+                        chars,
+                        min: config.min_chars,
+                    });
+                }
+                Ok(completion)
+            },
+        )
+        .await;
+        match result {
+            Ok(completion) => return Ok((completion, model.clone())),
+            Err(e) if e.is_unavailable() => last_error = Some(e),
+            Err(e) => return Err(PrError::Api(e.to_string()).into()),
+        }
+    }
+
+    match last_error {
+        Some(e @ CompletionError::TooShort { .. }) => Err(PrError::Api(format!(
+            "no model returned a usable description, the last one {}",
+            e
+        ))
+        .into()),
+        Some(e) => {
+            Err(PrError::Api(format!("all models are unavailable, last error: {}", e)).into())
+        }
+        None => Err("no model configured".into()),
+    }
+}
+
+/// A failed completion request.
+#[derive(Debug, thiserror::Error)]
+enum CompletionError {
+    /// The API answered with an error object.
+    #[error("{error_type}: {message}")]
+    Api {
+        status: StatusCode,
+        error_type: String,
+        message: String,
+        retry_after: Option<Duration>,
+    },
+    #[error("http error: {0}")]
+    Http(#[from] reqwest::Error),
+    /// The completion was empty or nearly so, e.g. cut off mid-stream.
+    #[error("was {chars} characters, below the minimum of {min}")]
+    TooShort { chars: usize, min: usize },
+}
+
+impl CompletionError {
+    /// Whether the model is overloaded, rate limited or temporarily down, as
+    /// opposed to a problem with the request itself.
+    fn is_unavailable(&self) -> bool {
+        match self {
+            CompletionError::Api {
+                status, error_type, ..
+            } => {
+                matches!(
+                    error_type.as_str(),
+                    "overloaded_error" | "api_error" | "rate_limit_error"
+                ) || status.is_server_error()
+            }
+            CompletionError::Http(e) => {
+                e.is_timeout()
+                    || e.is_connect()
+                    || e.status().is_some_and(|status| status.is_server_error())
+            }
+            CompletionError::TooShort { .. } => true,
+        }
+    }
+
+    fn retry_after(&self) -> Option<Duration> {
+        match self {
+            CompletionError::Api { retry_after, .. } => *retry_after,
+            CompletionError::Http(_) | CompletionError::TooShort { .. } => None,
+        }
+    }
+}
+
+/// Sends one completion request. The request goes out directly rather than
+/// through the anthropic client so the `Retry-After` header of a rate limited
+/// response can be read.
+async fn complete(
+    http: &reqwest::Client,
+    config: &ModelConfig,
+    request: &CompleteRequest,
+) -> Result<String, CompletionError> {
+    let response = http
+        .post(format!("{}/v1/complete", config.api_base))
+        .header("x-api-key", &config.api_key)
+        .header("anthropic-version", ANTHROPIC_VERSION)
+        .json(request)
+        .send()
+        .await?;
+
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response.json::<CompleteResponse>().await?.completion);
+    }
+
+    // Only the delay-seconds form is used by the API, HTTP dates are ignored
+    let retry_after = response
+        .headers()
+        .get(RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse().ok())
+        .map(Duration::from_secs);
+    let body: serde_json::Value = response.json().await.unwrap_or_default();
+    let error = &body["error"];
+    Err(CompletionError::Api {
+        status,
+        error_type: error["type"]
+            .as_str()
+            .unwrap_or("unknown_error")
+            .to_string(),
+        message: error["message"]
+            .as_str()
+            .map_or_else(|| status.to_string(), str::to_string),
+        retry_after,
+    })
+}
@@ -0,0 +1,458 @@
+use std::collections::BTreeMap;
+
+use crate::diff::DependencyUpdate;
+use crate::git::{AuthorCommits, FileStat};
+
+/// Keeps an embedded diff comfortably below GitHub's 65536 character body limit.
+const BODY_DIFF_MAX_CHARS: usize = 50_000;
+/// The longest body sent to the forge, leaving headroom below GitHub's limit.
+pub const DEFAULT_BODY_MAX_CHARS: usize = 60_000;
+const BODY_TRUNCATED: &str = "[description truncated]";
+
+/// A body made from the commit subjects alone: the subject itself for a single
+/// commit, otherwise one bullet per commit.
+pub fn commit_subjects_body(commit_messages: &[String]) -> String {
+    match commit_messages {
+        [subject] => subject.clone(),
+        subjects => subjects
+            .iter()
+            .map(|subject| format!("- {}", subject))
+            .collect::<Vec<_>>()
+            .join("\n"),
+    }
+}
+
+/// A body assembled without the model, for `--no-ai`: the commit subjects
+/// and a line of diff stats.
+pub fn deterministic_body(commit_messages: &[String], stats: &[FileStat]) -> String {
+    let mut body = String::new();
+    if !commit_messages.is_empty() {
+        body.push_str("## Commits\n\n");
+        for subject in commit_messages {
+            body.push_str(&format!("- {}\n", subject));
+        }
+        body.push('\n');
+    }
+
+    let added: u64 = stats.iter().filter_map(|stat| stat.added).sum();
+    let removed: u64 = stats.iter().filter_map(|stat| stat.removed).sum();
+    body.push_str(&format!(
+        "## Changes\n\n{} {} changed, {} insertions(+), {} deletions(-)",
+        stats.len(),
+        if stats.len() == 1 { "file" } else { "files" },
+        added,
+        removed
+    ));
+    body
+}
+
+/// A title made from the branch name, e.g. "Add login page" for
+/// `feature/add-login-page`.
+pub fn branch_title(branch: &str) -> String {
+    let name = branch
+        .rsplit('/')
+        .next()
+        .unwrap_or(branch)
+        .replace(['-', '_'], " ");
+    let mut chars = name.trim().chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => branch.to_string(),
+    }
+}
+
+/// Cleans up the model's output: strips ANSI escape sequences and control
+/// characters other than newlines and tabs. Whitespace is left to
+/// [`tidy_markdown`], which knows to keep fenced code as it is.
+pub fn sanitize_body(body: &str) -> String {
+    strip_ansi(body)
+        .chars()
+        .filter(|c| !c.is_control() || matches!(c, '\n' | '\t'))
+        .collect::<String>()
+        .trim_end()
+        .to_string()
+}
+
+/// Tidies the final body for rendering: a blank line around every heading, no
+/// trailing whitespace and no more than one blank line in a row. Fenced code,
+/// such as an embedded diff, is left exactly as it is.
+pub fn tidy_markdown(body: &str) -> String {
+    let mut lines: Vec<&str> = Vec::new();
+    let mut fence: Option<&str> = None;
+    let mut after_heading = false;
+
+    for line in body.lines() {
+        if let Some(marker) = fence {
+            lines.push(line);
+            let trimmed = line.trim();
+            if trimmed.starts_with(marker) && trimmed.trim_start_matches(marker).is_empty() {
+                fence = None;
+            }
+            continue;
+        }
+
+        let line = line.trim_end();
+        if line.is_empty() {
+            if lines.last().is_some_and(|last| !last.is_empty()) {
+                lines.push("");
+            }
+            after_heading = false;
+            continue;
+        }
+        let heading = is_heading(line);
+        if (heading || after_heading) && lines.last().is_some_and(|last| !last.is_empty()) {
+            lines.push("");
+        }
+        if let Some(marker) = fence_marker(line) {
+            fence = Some(marker);
+        }
+        lines.push(line);
+        after_heading = heading;
+    }
+
+    while lines.last() == Some(&"") {
+        lines.pop();
+    }
+    lines.join("\n")
+}
+
+/// `body` cut on a line boundary to at most `max_chars` characters, ending
+/// with a "[description truncated]" marker, or `None` when it already fits. A
+/// code fence left open by the cut is closed first.
+pub fn truncate_body(body: &str, max_chars: usize) -> Option<String> {
+    if body.chars().count() <= max_chars {
+        return None;
+    }
+
+    let marker_chars = BODY_TRUNCATED.len() + 1;
+    let mut kept = String::new();
+    let mut kept_chars = 0;
+    let mut fence: Option<&str> = None;
+    for line in body.lines() {
+        let next_fence = match fence {
+            Some(marker) => {
+                let trimmed = line.trim();
+                let closes =
+                    trimmed.starts_with(marker) && trimmed.trim_start_matches(marker).is_empty();
+                (!closes).then_some(marker)
+            }
+            None => fence_marker(line),
+        };
+        let line_chars = line.chars().count() + 1;
+        let closing_chars = next_fence.map_or(0, |marker| marker.len() + 1);
+        if kept_chars + line_chars + closing_chars + marker_chars > max_chars {
+            break;
+        }
+        kept.push_str(line);
+        kept.push('\n');
+        kept_chars += line_chars;
+        fence = next_fence;
+    }
+
+    if let Some(marker) = fence {
+        kept.push_str(marker);
+        kept.push('\n');
+    }
+    kept.push('\n');
+    kept.push_str(BODY_TRUNCATED);
+    Some(kept)
+}
+
+/// An ATX heading. `#123` at the start of a line is an issue reference, not
+/// a heading, and is left alone.
+fn is_heading(line: &str) -> bool {
+    let line = line.trim_start();
+    let hashes = line.chars().take_while(|&c| c == '#').count();
+    (1..=6).contains(&hashes) && line[hashes..].chars().next().is_none_or(|c| c == ' ')
+}
+
+/// The run of backticks or tildes opening a code fence.
+fn fence_marker(line: &str) -> Option<&str> {
+    let line = line.trim_start();
+    let fence_char = line.chars().next().filter(|c| matches!(c, '`' | '~'))?;
+    let len = line.chars().take_while(|&c| c == fence_char).count();
+    (len >= 3).then(|| &line[..len])
+}
+
+/// Hard-wraps prose to `width` columns. Code fences, tables, headings, HTML and
+/// indented code are left alone, and links and inline code spans are never split.
+pub fn wrap_body(body: &str, width: usize) -> String {
+    let mut wrapped = Vec::new();
+    let mut in_fence = false;
+
+    for line in body.lines() {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            in_fence = !in_fence;
+            wrapped.push(line.to_string());
+            continue;
+        }
+        let verbatim = in_fence
+            || line.chars().count() <= width
+            || trimmed.starts_with('|')
+            || trimmed.starts_with('#')
+            || trimmed.starts_with('<')
+            || line.starts_with("    ")
+            || line.starts_with('\t');
+        if verbatim {
+            wrapped.push(line.to_string());
+            continue;
+        }
+
+        let (first_prefix, rest_prefix, content) = line_prefixes(line);
+        let mut current = first_prefix;
+        let mut has_words = false;
+        for word in markdown_words(content) {
+            let fits = current.chars().count() + 1 + word.chars().count() <= width;
+            if has_words && !fits {
+                wrapped.push(current);
+                current = rest_prefix.clone();
+                has_words = false;
+            }
+            if has_words {
+                current.push(' ');
+            }
+            current.push_str(word);
+            has_words = true;
+        }
+        wrapped.push(current);
+    }
+
+    wrapped.join("\n")
+}
+
+/// Splits a line into the prefix for its first line (indent plus list marker or
+/// blockquote), the prefix for continuation lines, and the remaining content.
+fn line_prefixes(line: &str) -> (String, String, &str) {
+    let indent_len = line.len() - line.trim_start().len();
+    let (indent, rest) = line.split_at(indent_len);
+
+    if let Some(content) = rest.strip_prefix("> ") {
+        let prefix = format!("{}> ", indent);
+        return (prefix.clone(), prefix, content);
+    }
+
+    let marker_len = ["- ", "* ", "+ "]
+        .iter()
+        .find(|marker| rest.starts_with(*marker))
+        .map(|marker| marker.len())
+        .or_else(|| {
+            let digits = rest.chars().take_while(char::is_ascii_digit).count();
+            let after = &rest[digits..];
+            (digits > 0 && (after.starts_with(". ") || after.starts_with(") ")))
+                .then_some(digits + 2)
+        })
+        .unwrap_or(0);
+
+    let (marker, content) = rest.split_at(marker_len);
+    (
+        format!("{}{}", indent, marker),
+        " ".repeat(indent.len() + marker.len()),
+        content,
+    )
+}
+
+/// Splits on spaces, keeping `[links](...)` and `` `code` `` spans whole.
+fn markdown_words(text: &str) -> Vec<&str> {
+    let mut words = Vec::new();
+    let mut start = None;
+    let mut brackets = 0usize;
+    let mut parens = 0usize;
+    let mut in_code = false;
+    let mut previous = ' ';
+
+    for (i, c) in text.char_indices() {
+        match c {
+            '`' => in_code = !in_code,
+            '[' if !in_code => brackets += 1,
+            ']' if !in_code => brackets = brackets.saturating_sub(1),
+            '(' if !in_code && (previous == ']' || parens > 0) => parens += 1,
+            ')' if !in_code => parens = parens.saturating_sub(1),
+            _ => {}
+        }
+        let splittable = c == ' ' && !in_code && brackets == 0 && parens == 0;
+        if splittable {
+            if let Some(s) = start.take() {
+                words.push(&text[s..i]);
+            }
+        } else if start.is_none() {
+            start = Some(i);
+        }
+        previous = c;
+    }
+    if let Some(s) = start {
+        words.push(&text[s..]);
+    }
+
+    words
+}
+
+/// A markdown table of the changed files with their added and removed line
+/// counts, listing at most `max_rows` files.
+pub fn file_table_block(stats: &[FileStat], max_rows: usize) -> String {
+    if stats.is_empty() {
+        return String::new();
+    }
+
+    let count =
+        |n: Option<u64>, sign: char| n.map_or("binary".to_string(), |n| format!("{}{}", sign, n));
+    let mut block = "\n\n| File | Added | Removed |\n| ---- | ----: | ------: |\n".to_string();
+    for stat in stats.iter().take(max_rows) {
+        block.push_str(&format!(
+            "| `{}` | {} | {} |\n",
+            stat.path.replace('|', "\\|"),
+            count(stat.added, '+'),
+            count(stat.removed, '-')
+        ));
+    }
+    if stats.len() > max_rows {
+        block.push_str(&format!("\n... {} more files\n", stats.len() - max_rows));
+    }
+    block.truncate(block.trim_end().len());
+    block
+}
+
+/// A "## Dependency Updates" table of the packages whose locked versions
+/// changed, or nothing when none did.
+pub fn dependency_updates_block(updates: &[DependencyUpdate]) -> String {
+    if updates.is_empty() {
+        return String::new();
+    }
+
+    let mut block =
+        "\n\n## Dependency Updates\n\n| Package | From | To |\n| ------- | ---- | -- |\n"
+            .to_string();
+    for update in updates {
+        block.push_str(&format!(
+            "| `{}` | {} | {} |\n",
+            update.name,
+            update.from.as_deref().unwrap_or("added"),
+            update.to.as_deref().unwrap_or("removed")
+        ));
+    }
+    block.truncate(block.trim_end().len());
+    block
+}
+
+/// A "## Commits by Author" section listing each author's commit subjects,
+/// or nothing when there are no commits.
+pub fn commits_by_author_block(groups: &[AuthorCommits]) -> String {
+    if groups.is_empty() {
+        return String::new();
+    }
+
+    let mut block = "\n\n## Commits by Author\n".to_string();
+    for group in groups {
+        block.push_str(&format!("\n### {}\n\n", group.author));
+        for subject in &group.subjects {
+            block.push_str(&format!("- {}\n", subject));
+        }
+    }
+    block.truncate(block.trim_end().len());
+    block
+}
+
+/// Renders the diff as a collapsed `<details>` block, truncated on a line boundary
+/// so the body stays within the forge's size limit.
+pub fn diff_details_block(diff: &str) -> String {
+    let mut shown = String::new();
+    let mut truncated = false;
+    for line in diff.lines() {
+        if shown.len() + line.len() + 1 > BODY_DIFF_MAX_CHARS {
+            truncated = true;
+            break;
+        }
+        shown.push_str(line);
+        shown.push('\n');
+    }
+
+    // The fence has to be longer than any run of backticks inside the diff
+    let longest_run = diff
+        .split(|c| c != '`')
+        .map(str::len)
+        .max()
+        .unwrap_or_default();
+    let fence = "`".repeat(longest_run.max(2) + 1);
+
+    let mut block = format!(
+        "\n\n<details><summary>Diff</summary>\n\n{}diff\n{}{}\n",
+        fence, shown, fence
+    );
+    if truncated {
+        block.push_str("\n_Diff truncated._\n");
+    }
+    block.push_str("\n</details>");
+    block
+}
+
+/// The teams to mention: those given explicitly, then those mapped to a path
+/// prefix that one of the changed files starts with, each once and with a
+/// leading `@`.
+pub fn mentioned_teams(
+    explicit: &[String],
+    by_path: &BTreeMap<String, Vec<String>>,
+    changed_paths: &[&str],
+) -> Vec<String> {
+    let mapped = by_path
+        .iter()
+        .filter(|(prefix, _)| changed_paths.iter().any(|path| path.starts_with(*prefix)))
+        .flat_map(|(_, teams)| teams);
+
+    let mut mentions = Vec::new();
+    for team in explicit.iter().chain(mapped) {
+        let team = format!("@{}", team.trim().trim_start_matches('@'));
+        if team.len() > 1 && !mentions.contains(&team) {
+            mentions.push(team);
+        }
+    }
+    mentions
+}
+
+/// A "cc" line mentioning `teams`, so they are notified without being asked
+/// for a review.
+pub fn mentions_block(teams: &[String]) -> String {
+    if teams.is_empty() {
+        String::new()
+    } else {
+        format!("\n\ncc {}", teams.join(" "))
+    }
+}
+
+/// Removes CSI and OSC escape sequences, such as colors and hyperlinks.
+pub fn strip_ansi(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c != '\x1b' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            // CSI: ESC [ parameters... final byte in @..~
+            Some('[') => {
+                for c in chars.by_ref() {
+                    if ('@'..='~').contains(&c) {
+                        break;
+                    }
+                }
+            }
+            // OSC: ESC ] ... terminated by BEL or ESC \
+            Some(']') => {
+                while let Some(c) = chars.next() {
+                    if c == '\x07' {
+                        break;
+                    }
+                    if c == '\x1b' && chars.peek() == Some(&'\\') {
+                        chars.next();
+                        break;
+                    }
+                }
+            }
+            // Any other two-character escape
+            _ => {}
+        }
+    }
+
+    out
+}
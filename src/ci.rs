@@ -0,0 +1,29 @@
+/// What the GitHub Actions environment says about the run. The refs are only
+/// set for `pull_request` events.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GithubActions {
+    /// `GITHUB_BASE_REF`, the branch the pull request targets.
+    pub base_ref: Option<String>,
+    /// `GITHUB_HEAD_REF`, the branch the pull request comes from.
+    pub head_ref: Option<String>,
+}
+
+impl GithubActions {
+    /// Reads the Actions environment, or `None` when not running in Actions.
+    pub fn detect() -> Option<Self> {
+        Self::from_vars(|name| std::env::var(name).ok())
+    }
+
+    /// [`detect`](Self::detect) with the variables looked up through `var`.
+    pub fn from_vars(var: impl Fn(&str) -> Option<String>) -> Option<Self> {
+        if var("GITHUB_ACTIONS").as_deref() != Some("true") {
+            return None;
+        }
+        let non_empty = |name| var(name).filter(|value| !value.is_empty());
+
+        Some(GithubActions {
+            base_ref: non_empty("GITHUB_BASE_REF"),
+            head_ref: non_empty("GITHUB_HEAD_REF"),
+        })
+    }
+}
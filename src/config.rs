@@ -0,0 +1,182 @@
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::diff::{LargeDiff, TruncateStrategy};
+use crate::git::get_repo_root;
+
+pub const CONFIG_FILE: &str = ".pullrequest.toml";
+/// Patterns of files to leave out of the diff, one per line.
+pub const IGNORE_FILE: &str = ".pullrequestignore";
+
+/// Repository-level settings read from `.pullrequest.toml`. Command line flags
+/// take precedence over anything set here.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Branch pull requests target unless `--base` is given, e.g. `develop`.
+    pub base: Option<String>,
+    /// Model that writes the title and description unless `--model` is given.
+    pub model: Option<String>,
+    /// Shell command run before the diff is gathered.
+    pub pre_hook: Option<String>,
+    /// Shell command run after the pull request is created.
+    pub post_hook: Option<String>,
+    /// Shell command that prints the Anthropic API key.
+    pub key_command: Option<String>,
+    /// Read the Anthropic API key from the OS keychain.
+    pub keychain: bool,
+    /// With `--brief`, diffs changing fewer lines than this skip the model.
+    pub brief_threshold: Option<usize>,
+    /// URL of a shared prompt template replacing the default instructions.
+    pub template_url: Option<String>,
+    /// Generated descriptions shorter than this many characters are retried.
+    pub min_description_chars: Option<usize>,
+    /// Replaces the `\n\nHuman:` marker the prompt starts with.
+    pub human_prompt: Option<String>,
+    /// Replaces the `\n\nAssistant:` marker the prompt ends with.
+    pub ai_prompt: Option<String>,
+    /// Sequences that end the completion, `\n\nHuman:` when unset.
+    pub stop_sequences: Option<Vec<String>>,
+    /// Diffs longer than this many lines are truncated or summarized.
+    pub max_diff_lines: Option<usize>,
+    /// Diff lines longer than this many characters are replaced with a marker,
+    /// 0 to keep them.
+    pub max_line_length: Option<usize>,
+    /// How similar, in percent, a moved file must stay to be described as a
+    /// rename rather than a deletion and a new file.
+    pub rename_threshold: Option<u8>,
+    /// Bodies longer than this many characters are cut short with a marker.
+    pub body_max_chars: Option<usize>,
+    /// What to do with a diff over `max_diff_lines` without asking.
+    pub large_diff: Option<LargeDiff>,
+    /// How truncation shares `max_diff_lines` between files.
+    pub truncate_strategy: Option<TruncateStrategy>,
+    /// Refuse to open a pull request that links no issue.
+    pub require_issue: bool,
+    /// GitHub Projects new pull requests are added to, unless `--project` is given.
+    pub projects: Vec<String>,
+    /// Teams mentioned in the body when a changed file starts with the path
+    /// prefix they are listed under, e.g. `"src/api/" = ["@org/api"]`.
+    pub mentions: BTreeMap<String, Vec<String>>,
+    /// GitHub logins that `--round-robin` picks reviewers from, in order.
+    pub team: Vec<String>,
+    /// Team members to leave out of the rotation while they're away.
+    pub out_of_office: Vec<String>,
+    /// Logins never requested for review, e.g. bots, on top of `--reviewers-exclude`.
+    pub reviewers_exclude: Vec<String>,
+    /// How many reviewers `--round-robin` requests, 1 when unset.
+    pub round_robin_reviewers: Option<usize>,
+    /// Refuse to create a pull request with fewer reviewers than this.
+    pub min_reviewers: Option<usize>,
+    /// Words in the branch name or at the start of the latest commit subject
+    /// that open the pull request as a draft, `["wip", "draft"]` when unset.
+    pub wip_patterns: Option<Vec<String>>,
+}
+
+impl Config {
+    /// Loads the config from the repository root, or the defaults when there is none.
+    pub fn load(repo: &Path) -> Result<Config, Box<dyn std::error::Error>> {
+        let path = get_repo_root(repo)?.join(CONFIG_FILE);
+        if !path.exists() {
+            return Ok(Config::default());
+        }
+
+        let contents = std::fs::read_to_string(&path)?;
+        let config: Config =
+            toml::from_str(&contents).map_err(|e| format!("Invalid {}: {}", path.display(), e))?;
+        if config
+            .stop_sequences
+            .iter()
+            .flatten()
+            .any(|stop| stop.is_empty())
+        {
+            return Err(format!(
+                "Invalid {}: stop_sequences can't be empty strings",
+                path.display()
+            )
+            .into());
+        }
+        Ok(config)
+    }
+}
+
+/// The patterns in the repository's `.pullrequestignore`, skipping blank lines
+/// and `#` comments. Empty when there is no such file.
+pub fn load_ignore_patterns(repo: &Path) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let path = get_repo_root(repo)?.join(IGNORE_FILE);
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+
+    Ok(std::fs::read_to_string(path)?
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(String::from)
+        .collect())
+}
+
+/// A commented `.pullrequest.toml` for `pullrequest init`, targeting `base`
+/// and generating with `model`.
+pub fn starter_config(base: &str, model: &str) -> String {
+    format!(
+        "# Settings for pullrequest. Command line flags take precedence over these.
+
+# Branch pull requests target unless --base is given
+base = {}
+
+# Model that writes the title and description unless --model is given
+model = {}
+
+# Diffs longer than this many lines are truncated or summarized
+# max_diff_lines = 8000
+
+# Logins never requested for review
+# reviewers_exclude = [\"dependabot[bot]\"]
+",
+        toml::Value::from(base),
+        toml::Value::from(model)
+    )
+}
+
+/// The example `.pullrequestignore` written by `pullrequest init`.
+pub const STARTER_IGNORE: &str =
+    "# Files left out of the diff the description is written from, one pattern
+# per line. Lockfiles and generated code rarely say anything about a change.
+Cargo.lock
+package-lock.json
+yarn.lock
+*.min.js
+";
+
+/// Writes `config` as `.pullrequest.toml` and [`STARTER_IGNORE`] as
+/// `.pullrequestignore` at the repository root, returning the paths written.
+/// Nothing is written when either file exists, unless `force` is set.
+pub fn write_starter_files(
+    repo: &Path,
+    config: &str,
+    force: bool,
+) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
+    let root = get_repo_root(repo)?;
+    let files = [
+        (root.join(CONFIG_FILE), config),
+        (root.join(IGNORE_FILE), STARTER_IGNORE),
+    ];
+    if !force {
+        if let Some((path, _)) = files.iter().find(|(path, _)| path.exists()) {
+            return Err(format!(
+                "{} already exists, pass --force to overwrite it",
+                path.display()
+            )
+            .into());
+        }
+    }
+
+    let mut written = Vec::new();
+    for (path, contents) in files {
+        std::fs::write(&path, contents)?;
+        written.push(path);
+    }
+    Ok(written)
+}
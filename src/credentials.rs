@@ -0,0 +1,102 @@
+use std::path::PathBuf;
+use std::process::Command;
+
+/// Service and account the API key is stored under in the OS keychain.
+pub const KEYCHAIN_SERVICE: &str = "pullrequest";
+pub const KEYCHAIN_ACCOUNT: &str = "anthropic";
+
+/// An API key given on the command line, shown as `ApiKey(..)` when debug
+/// printed so it can't end up in logs.
+#[derive(Clone, PartialEq, Eq)]
+pub struct ApiKey(pub String);
+
+impl std::fmt::Debug for ApiKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("ApiKey(..)")
+    }
+}
+
+impl std::str::FromStr for ApiKey {
+    type Err = String;
+
+    fn from_str(key: &str) -> Result<Self, Self::Err> {
+        let key = key.trim();
+        if key.is_empty() {
+            return Err("the API key is empty".to_string());
+        }
+        Ok(ApiKey(key.to_string()))
+    }
+}
+
+/// Where to look for the Anthropic API key, in order of precedence.
+#[derive(Debug, Default, Clone)]
+pub struct KeySource {
+    /// The key itself.
+    pub key: Option<ApiKey>,
+    /// File holding the key, e.g. a mounted CI secret.
+    pub key_file: Option<PathBuf>,
+    /// Shell command whose stdout is the key.
+    pub command: Option<String>,
+    /// Read the key from the OS keychain.
+    pub keychain: bool,
+}
+
+/// Resolves the API key from the configured source, falling back to the
+/// `ANTHROPIC_KEY` environment variable when none is configured.
+pub fn resolve_api_key(source: &KeySource) -> Result<String, Box<dyn std::error::Error>> {
+    if let Some(ApiKey(key)) = &source.key {
+        return Ok(key.clone());
+    }
+
+    if let Some(path) = &source.key_file {
+        let key = std::fs::read_to_string(path)
+            .map_err(|e| format!("Could not read the API key from {}: {}", path.display(), e))?;
+        let key = key.trim();
+        if key.is_empty() {
+            return Err(format!("Key file {} is empty", path.display()).into());
+        }
+        return Ok(key.to_string());
+    }
+
+    if let Some(command) = &source.command {
+        return key_from_command(command);
+    }
+
+    if source.keychain {
+        let entry = keyring::Entry::new(KEYCHAIN_SERVICE, KEYCHAIN_ACCOUNT)?;
+        return entry.get_password().map_err(|e| {
+            format!(
+                "Could not read the API key from the keychain (service `{}`, account `{}`): {}",
+                KEYCHAIN_SERVICE, KEYCHAIN_ACCOUNT, e
+            )
+            .into()
+        });
+    }
+
+    std::env::var("ANTHROPIC_KEY").map_err(|_| {
+        "No Anthropic API key: set ANTHROPIC_KEY, or use --api-key, --api-key-file, \
+         --key-command or --keychain"
+            .into()
+    })
+}
+
+fn key_from_command(command: &str) -> Result<String, Box<dyn std::error::Error>> {
+    let output = Command::new("sh").args(["-c", command]).output()?;
+
+    if !output.status.success() {
+        return Err(format!(
+            "Key command `{}` failed ({}): {}",
+            command,
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+
+    let key = String::from_utf8(output.stdout)?.trim().to_string();
+    if key.is_empty() {
+        return Err(format!("Key command `{}` printed nothing", command).into());
+    }
+
+    Ok(key)
+}
@@ -0,0 +1,818 @@
+//! Transformations applied to the diff before it is sent to the model.
+
+use clap::ValueEnum;
+use serde::Deserialize;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Splits a unified diff into per-file sections, each starting at its
+/// `diff --git` header. Anything before the first header is its own section.
+pub fn file_sections(diff: &str) -> Vec<&str> {
+    let mut sections = Vec::new();
+    let mut start = 0;
+
+    for (offset, _) in diff.match_indices("diff --git ") {
+        if offset != 0 && diff.as_bytes()[offset - 1] != b'\n' {
+            continue;
+        }
+        if offset > start {
+            sections.push(&diff[start..offset]);
+        }
+        start = offset;
+    }
+    if start < diff.len() {
+        sections.push(&diff[start..]);
+    }
+
+    sections
+}
+
+/// The post-image path of a file section, taken from its `diff --git` header.
+pub fn section_path(section: &str) -> Option<&str> {
+    let header = section.lines().next()?.strip_prefix("diff --git ")?;
+    header.rsplit_once(" b/").map(|(_, path)| path)
+}
+
+pub const DEFAULT_MAX_DIFF_LINES: usize = 8_000;
+
+/// What to do with a diff longer than the prompt allows.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LargeDiff {
+    /// Send only the first files that fit
+    #[default]
+    Truncate,
+    /// Summarize the diff in chunks and describe the summaries
+    Summarize,
+    /// Stop without generating a description
+    Abort,
+}
+
+/// Keeps as many whole files of the diff as fit in `max_lines` lines, or the
+/// start of the first file when even that is too long, and notes how many
+/// lines were left out.
+pub fn truncate_diff(diff: &str, max_lines: usize) -> String {
+    let total = diff.lines().count();
+    if total <= max_lines {
+        return diff.to_string();
+    }
+
+    let mut kept = String::new();
+    let mut kept_lines = 0;
+    for section in file_sections(diff) {
+        let lines = section.lines().count();
+        if kept_lines + lines > max_lines {
+            if kept_lines == 0 {
+                for line in section.lines().take(max_lines) {
+                    kept.push_str(line);
+                    kept.push('\n');
+                }
+                kept_lines = max_lines;
+            }
+            break;
+        }
+        kept.push_str(section);
+        kept_lines += lines;
+    }
+    if !kept.ends_with('\n') {
+        kept.push('\n');
+    }
+    kept.push_str(&format!(
+        "... {} more lines of the diff were left out\n",
+        total - kept_lines
+    ));
+    kept
+}
+
+/// Which files lose lines when a diff is truncated.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TruncateStrategy {
+    /// Keep whole files from the start and drop the files after them
+    #[default]
+    Tail,
+    /// Give each file an even share, cutting the largest files first
+    Even,
+}
+
+/// Keeps part of every file of the diff within `max_lines` lines in total.
+/// Files shorter than an even share are kept whole and the rest of the budget
+/// is split between the longer ones, each cut short with a note of how many
+/// of its lines were left out.
+pub fn truncate_diff_evenly(diff: &str, max_lines: usize) -> String {
+    let sections = file_sections(diff);
+    let lengths: Vec<usize> = sections.iter().map(|s| s.lines().count()).collect();
+    if lengths.iter().sum::<usize>() <= max_lines {
+        return diff.to_string();
+    }
+
+    let mut by_length: Vec<usize> = (0..sections.len()).collect();
+    by_length.sort_by_key(|&i| lengths[i]);
+    let mut caps = vec![0; sections.len()];
+    let mut remaining = max_lines;
+    for (done, &i) in by_length.iter().enumerate() {
+        let share = remaining / (sections.len() - done);
+        // Every file keeps at least its header
+        caps[i] = lengths[i].min(share).max(1);
+        remaining = remaining.saturating_sub(caps[i]);
+    }
+
+    let mut kept = String::new();
+    for (i, section) in sections.iter().enumerate() {
+        if caps[i] >= lengths[i] {
+            kept.push_str(section);
+            if !kept.ends_with('\n') {
+                kept.push('\n');
+            }
+            continue;
+        }
+        for line in section.lines().take(caps[i]) {
+            kept.push_str(line);
+            kept.push('\n');
+        }
+        kept.push_str(&format!(
+            "... {} more lines of this file were left out\n",
+            lengths[i] - caps[i]
+        ));
+    }
+    kept
+}
+
+/// Groups whole files of the diff into chunks of at most `max_lines` lines,
+/// truncating any single file that is longer than that.
+pub fn diff_chunks(diff: &str, max_lines: usize) -> Vec<String> {
+    let mut chunks = Vec::new();
+    let mut current = String::new();
+    let mut current_lines = 0;
+    for section in file_sections(diff) {
+        let lines = section.lines().count();
+        if current_lines + lines > max_lines && !current.is_empty() {
+            chunks.push(std::mem::take(&mut current));
+            current_lines = 0;
+        }
+        if lines > max_lines {
+            chunks.push(truncate_diff(section, max_lines));
+            continue;
+        }
+        current.push_str(section);
+        current_lines += lines;
+    }
+    if !current.is_empty() {
+        chunks.push(current);
+    }
+
+    chunks
+}
+
+/// Lines longer than this many characters are taken to be minified or
+/// generated and left out of the diff.
+pub const DEFAULT_MAX_LINE_LENGTH: usize = 500;
+
+/// Replaces hunk lines longer than `max_chars` characters, typically minified
+/// JavaScript or CSS, with a marker noting their length. The `+`, `-` or ` `
+/// prefix is kept so the change still shows. File and hunk headers are left
+/// alone however long their paths are.
+pub fn omit_long_lines(diff: &str, max_chars: usize) -> String {
+    let mut omitted = String::with_capacity(diff.len());
+    let mut in_hunk = false;
+    for line in diff.split_inclusive('\n') {
+        let content = line.trim_end_matches('\n');
+        if content.starts_with("diff --git ") {
+            in_hunk = false;
+        } else if content.starts_with("@@") {
+            in_hunk = true;
+        }
+        let Some(prefix @ ('+' | '-' | ' ')) = content.chars().next().filter(|_| in_hunk) else {
+            omitted.push_str(line);
+            continue;
+        };
+        let chars = content.chars().count();
+        if chars <= max_chars {
+            omitted.push_str(line);
+            continue;
+        }
+        omitted.push_str(&format!(
+            "{}[minified line, {} chars, omitted]\n",
+            prefix, chars
+        ));
+    }
+    omitted
+}
+
+/// Converts CRLF line endings to LF and drops UTF-8 byte order marks, which
+/// only cost tokens and confuse the model.
+pub fn normalize_text(diff: &str) -> String {
+    diff.replace("\r\n", "\n").replace('\u{feff}', "")
+}
+
+/// Number of added and removed lines, not counting file headers.
+pub fn changed_line_count(diff: &str) -> usize {
+    diff.lines()
+        .filter(|line| {
+            (line.starts_with('+') && !line.starts_with("+++"))
+                || (line.starts_with('-') && !line.starts_with("---"))
+        })
+        .count()
+}
+
+/// Removed lines declaring public Rust items, minus any added back unchanged
+/// elsewhere in the diff. Each is a removed, renamed or re-signatured item,
+/// so the list grounds a breaking change check.
+pub fn removed_public_items(diff: &str) -> Vec<String> {
+    let declaration = |line: &str| {
+        let line = line.trim();
+        let public = line.strip_prefix("pub ")?;
+        let item = public
+            .trim_start_matches("async ")
+            .trim_start_matches("unsafe ");
+        [
+            "fn ", "struct ", "enum ", "trait ", "type ", "const ", "static ", "mod ",
+        ]
+        .iter()
+        .any(|keyword| item.starts_with(keyword))
+        .then(|| line.to_string())
+    };
+    let added: Vec<String> = diff
+        .lines()
+        .filter(|line| !line.starts_with("+++"))
+        .filter_map(|line| line.strip_prefix('+'))
+        .filter_map(declaration)
+        .collect();
+
+    diff.lines()
+        .filter(|line| !line.starts_with("---"))
+        .filter_map(|line| line.strip_prefix('-'))
+        .filter_map(declaration)
+        .filter(|item| !added.contains(item))
+        .collect()
+}
+
+/// Paths of the changed files that are tests or CI configuration, which a test
+/// plan can be based on.
+pub fn test_paths(diff: &str) -> Vec<&str> {
+    file_sections(diff)
+        .into_iter()
+        .filter_map(section_path)
+        .filter(|path| is_test_path(path))
+        .collect()
+}
+
+fn is_test_path(path: &str) -> bool {
+    let file = path.rsplit('/').next().unwrap_or(path);
+    let in_test_dir = path
+        .split('/')
+        .any(|dir| matches!(dir, "test" | "tests" | "spec" | "__tests__"));
+    let ci = path.starts_with(".github/workflows/")
+        || path.starts_with(".circleci/")
+        || file == ".gitlab-ci.yml"
+        || file == "Jenkinsfile";
+
+    in_test_dir
+        || ci
+        || file.starts_with("test_")
+        || file.contains("_test.")
+        || file.contains(".test.")
+        || file.contains(".spec.")
+}
+
+/// The new-side line numbers of each file that appear in the diff, added or
+/// context, which are the lines an inline review comment can be left on.
+pub fn commentable_lines(diff: &str) -> BTreeMap<String, BTreeSet<u32>> {
+    let mut lines = BTreeMap::new();
+    for section in file_sections(diff) {
+        let Some(path) = section_path(section) else {
+            continue;
+        };
+        let mut file_lines = BTreeSet::new();
+        let mut next = None;
+        for line in section.lines() {
+            if let Some(hunk) = line.strip_prefix("@@ ") {
+                next = hunk
+                    .split_whitespace()
+                    .find_map(|range| range.strip_prefix('+'))
+                    .and_then(|range| range.split(',').next()?.parse::<u32>().ok());
+                continue;
+            }
+            let Some(number) = next.as_mut() else {
+                continue;
+            };
+            if line.starts_with('+') || line.starts_with(' ') {
+                file_lines.insert(*number);
+                *number += 1;
+            }
+        }
+        if !file_lines.is_empty() {
+            lines.insert(path.to_string(), file_lines);
+        }
+    }
+
+    lines
+}
+
+/// Drops the files matching any of `patterns` from the diff. Returns the
+/// remaining diff and each dropped path with the pattern that matched it.
+///
+/// Patterns follow `.gitignore` loosely: `*` and `?` match within a path
+/// segment and `**` across segments, a pattern without a `/` matches the
+/// file name at any depth, and a trailing `/` matches everything below a
+/// directory.
+pub fn exclude_files(diff: &str, patterns: &[String]) -> (String, Vec<(String, String)>) {
+    let mut excluded = Vec::new();
+    let diff = file_sections(diff)
+        .into_iter()
+        .filter(|section| {
+            let Some(path) = section_path(section) else {
+                return true;
+            };
+            match patterns.iter().find(|pattern| is_excluded(path, pattern)) {
+                Some(pattern) => {
+                    excluded.push((path.to_string(), pattern.clone()));
+                    false
+                }
+                None => true,
+            }
+        })
+        .collect();
+
+    (diff, excluded)
+}
+
+fn is_excluded(path: &str, pattern: &str) -> bool {
+    let pattern = pattern.trim();
+    if let Some(dir) = pattern.strip_suffix('/') {
+        let dir = dir.trim_start_matches('/');
+        let dir = if dir.contains('/') {
+            dir.to_string()
+        } else {
+            format!("**/{}", dir)
+        };
+        return glob_match(&format!("{}/**", dir), path);
+    }
+    match pattern.strip_prefix('/') {
+        Some(anchored) => glob_match(anchored, path),
+        None if pattern.contains('/') => glob_match(pattern, path),
+        None => glob_match(pattern, path.rsplit('/').next().unwrap_or(path)),
+    }
+}
+
+fn glob_match(pattern: &str, text: &str) -> bool {
+    fn matches(pattern: &[u8], text: &[u8]) -> bool {
+        match pattern {
+            [] => text.is_empty(),
+            [b'*', b'*', b'/', rest @ ..] => {
+                // `**/` also matches no directories at all
+                matches(rest, text)
+                    || (0..text.len()).any(|i| text[i] == b'/' && matches(rest, &text[i + 1..]))
+            }
+            [b'*', b'*', rest @ ..] => (0..=text.len()).any(|i| matches(rest, &text[i..])),
+            [b'*', rest @ ..] => (0..=text.len())
+                .take_while(|&i| i == 0 || text[i - 1] != b'/')
+                .any(|i| matches(rest, &text[i..])),
+            [b'?', rest @ ..] => {
+                text.first().is_some_and(|&c| c != b'/') && matches(rest, &text[1..])
+            }
+            [c, rest @ ..] => text.first() == Some(c) && matches(rest, &text[1..]),
+        }
+    }
+    matches(pattern.as_bytes(), text.as_bytes())
+}
+
+/// The files of `before` that `after`, a truncated version of it, cuts short
+/// or leaves out, each with a note of how much of it was kept.
+pub fn truncated_files(before: &str, after: &str) -> Vec<String> {
+    let kept: BTreeMap<&str, usize> = file_sections(after)
+        .into_iter()
+        .filter_map(|section| {
+            let lines = section
+                .lines()
+                .filter(|line| !line.starts_with("... ") || !line.ends_with("left out"))
+                .count();
+            Some((section_path(section)?, lines))
+        })
+        .collect();
+
+    file_sections(before)
+        .into_iter()
+        .filter_map(|section| {
+            let path = section_path(section)?;
+            let total = section.lines().count();
+            match kept.get(path) {
+                Some(&lines) if lines >= total => None,
+                Some(&lines) => Some(format!(
+                    "{}: truncated to {} of {} lines",
+                    path, lines, total
+                )),
+                None => Some(format!("{}: left out by truncation", path)),
+            }
+        })
+        .collect()
+}
+
+const REDACTED: &str = "[REDACTED]";
+
+/// Key names, lowercased, whose assigned values are treated as secrets when
+/// a name ends with one of them.
+const SECRET_NAMES: [&str; 7] = [
+    "password",
+    "passwd",
+    "secret",
+    "token",
+    "apikey",
+    "api_key",
+    "private_key",
+];
+
+/// Prefixes of well-known credential formats: AWS access keys, GitHub,
+/// Slack, Anthropic, Stripe and Google API tokens.
+const TOKEN_PREFIXES: [&str; 12] = [
+    "AKIA",
+    "ghp_",
+    "gho_",
+    "ghs_",
+    "ghu_",
+    "github_pat_",
+    "xoxb-",
+    "xoxp-",
+    "sk-ant-",
+    "sk_live_",
+    "rk_live_",
+    "AIza",
+];
+
+/// Replaces the lines of the diff that look like secrets with `[REDACTED]`,
+/// keeping the key of an assignment such as `API_KEY=[REDACTED]`, and leaves
+/// the rest of each file's diff intact. In environment files, keys and
+/// certificates every line of a hunk counts as a secret, context included.
+/// Returns the redacted diff and each path that had lines redacted, with how
+/// many.
+pub fn redact_secrets(diff: &str) -> (String, Vec<(String, usize)>) {
+    let mut redacted = Vec::new();
+    let diff = file_sections(diff)
+        .into_iter()
+        .map(|section| {
+            let path = section_path(section);
+            let sensitive = path.is_some_and(is_sensitive_path);
+            let mut count = 0;
+            let mut in_hunk = false;
+            let mut in_key = false;
+            let mut out = String::with_capacity(section.len());
+
+            for line in section.split_inclusive('\n') {
+                let (content, newline) = match line.strip_suffix('\n') {
+                    Some(content) => (content, "\n"),
+                    None => (line, ""),
+                };
+                if content.starts_with("@@") {
+                    in_hunk = true;
+                }
+                let Some(marker @ ('+' | '-' | ' ')) = content.chars().next().filter(|_| in_hunk)
+                else {
+                    out.push_str(line);
+                    continue;
+                };
+                match redact_line(&content[1..], sensitive, &mut in_key) {
+                    Some(replacement) => {
+                        count += 1;
+                        out.push(marker);
+                        out.push_str(&replacement);
+                        out.push_str(newline);
+                    }
+                    None => out.push_str(line),
+                }
+            }
+
+            if let (Some(path), true) = (path, count > 0) {
+                redacted.push((path.to_string(), count));
+            }
+            out
+        })
+        .collect();
+
+    (diff, redacted)
+}
+
+/// The redacted version of one line of a hunk, or `None` when it doesn't look
+/// like a secret. `in_key` tracks whether the line is inside a PEM private key.
+fn redact_line(text: &str, sensitive: bool, in_key: &mut bool) -> Option<String> {
+    let trimmed = text.trim();
+    if trimmed.starts_with("-----BEGIN") && trimmed.contains("PRIVATE KEY") {
+        *in_key = true;
+    }
+    if *in_key {
+        *in_key = !trimmed.starts_with("-----END");
+        return Some(REDACTED.to_string());
+    }
+
+    if let Some((key, value)) = assignment(text) {
+        let secret_name = key
+            .split(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+            .map(str::to_lowercase)
+            .any(|name| SECRET_NAMES.iter().any(|secret| name.ends_with(secret)));
+        if sensitive || (secret_name && is_literal_value(value)) {
+            return Some(format!("{}{}", key, REDACTED));
+        }
+    }
+    if contains_token(text) || (sensitive && !trimmed.is_empty() && !trimmed.starts_with('#')) {
+        return Some(REDACTED.to_string());
+    }
+    None
+}
+
+/// Splits `NAME=value`, `name: value` and `let name: &str = value` into
+/// everything up to the value, separator and spacing included, and the value.
+fn assignment(text: &str) -> Option<(&str, &str)> {
+    let separator = text.find('=').or_else(|| text.find(':'))?;
+    let key = &text[..separator];
+    if key.trim().is_empty() || text[separator + 1..].starts_with(['=', ':']) {
+        return None;
+    }
+    let value = text[separator + 1..].trim_start();
+    Some((&text[..text.len() - value.len()], value))
+}
+
+/// Whether an assigned value is a literal rather than an expression or a type,
+/// e.g. `"hunter22"` or `hunter22` but not `String` or `config.password`.
+fn is_literal_value(value: &str) -> bool {
+    let value = value.trim_end_matches([',', ';']).trim();
+    let quoted = [('"', '"'), ('\'', '\'')]
+        .iter()
+        .find_map(|(open, close)| value.strip_prefix(*open)?.strip_suffix(*close));
+    match quoted {
+        Some(inner) => inner.chars().count() >= 6,
+        None => {
+            value.chars().count() >= 8
+                && !value.contains(|c: char| c.is_whitespace() || "()<>{}[].&".contains(c))
+        }
+    }
+}
+
+/// Whether `text` contains a credential with a well-known prefix.
+fn contains_token(text: &str) -> bool {
+    let is_token_char = |c: char| c.is_ascii_alphanumeric() || c == '_' || c == '-';
+    TOKEN_PREFIXES.iter().any(|prefix| {
+        text.match_indices(prefix).any(|(start, _)| {
+            let standalone = !text[..start].ends_with(|c: char| c.is_ascii_alphanumeric());
+            let rest = text[start + prefix.len()..]
+                .chars()
+                .take_while(|&c| is_token_char(c))
+                .count();
+            standalone && rest >= 16
+        })
+    })
+}
+
+fn is_sensitive_path(path: &str) -> bool {
+    let file = path.rsplit('/').next().unwrap_or(path);
+    file.starts_with(".env")
+        || file.starts_with("id_rsa")
+        || file.ends_with(".pem")
+        || file.ends_with(".key")
+}
+
+/// Lockfiles whose version bumps [`dependency_updates`] reads.
+pub const LOCKFILES: [&str; 2] = ["Cargo.lock", "package-lock.json"];
+
+/// A package whose locked version changed. `from` is `None` for an added
+/// package and `to` for a removed one; several locked versions are joined
+/// with commas.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DependencyUpdate {
+    pub name: String,
+    pub from: Option<String>,
+    pub to: Option<String>,
+}
+
+/// The version changes in the diff's `Cargo.lock` and `package-lock.json`
+/// files, sorted by package name. A version is only attributed to a package
+/// whose name line is within the hunk, which git's default context covers.
+pub fn dependency_updates(diff: &str) -> Vec<DependencyUpdate> {
+    let mut removed: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+    let mut added: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+
+    for section in file_sections(diff) {
+        let Some(path) = section_path(section) else {
+            continue;
+        };
+        let file = path.rsplit('/').next().unwrap_or(path);
+        if !LOCKFILES.contains(&file) {
+            continue;
+        }
+        let cargo = file == "Cargo.lock";
+
+        let mut old_name: Option<String> = None;
+        let mut new_name: Option<String> = None;
+        let mut in_hunk = false;
+        for line in section.lines() {
+            if line.starts_with("@@") {
+                in_hunk = true;
+                old_name = None;
+                new_name = None;
+                continue;
+            }
+            let Some(marker @ ('+' | '-' | ' ')) = line.chars().next().filter(|_| in_hunk) else {
+                continue;
+            };
+            let text = line[1..].trim();
+            let parsed = if cargo {
+                cargo_lock_line(text)
+            } else {
+                npm_lock_line(text)
+            };
+            match parsed {
+                LockLine::Entry(name) => {
+                    if marker != '+' {
+                        old_name = name.clone();
+                    }
+                    if marker != '-' {
+                        new_name = name;
+                    }
+                }
+                LockLine::Version(version) => {
+                    let (versions, name) = match marker {
+                        '-' => (&mut removed, &old_name),
+                        '+' => (&mut added, &new_name),
+                        _ => continue,
+                    };
+                    if let Some(name) = name {
+                        versions
+                            .entry(name.clone())
+                            .or_default()
+                            .insert(version.to_string());
+                    }
+                }
+                LockLine::Other => {}
+            }
+        }
+    }
+
+    let names: BTreeSet<&String> = removed.keys().chain(added.keys()).collect();
+    names
+        .into_iter()
+        .filter(|name| removed.get(*name) != added.get(*name))
+        .map(|name| {
+            let joined = |versions: Option<&BTreeSet<String>>| {
+                versions.map(|versions| versions.iter().cloned().collect::<Vec<_>>().join(", "))
+            };
+            DependencyUpdate {
+                name: name.clone(),
+                from: joined(removed.get(name)),
+                to: joined(added.get(name)),
+            }
+        })
+        .collect()
+}
+
+/// One line of a lockfile, as far as [`dependency_updates`] cares.
+enum LockLine<'a> {
+    /// The start of an entry, with the package's name unless it's some
+    /// other kind of entry.
+    Entry(Option<String>),
+    /// The locked version of the current entry.
+    Version(&'a str),
+    Other,
+}
+
+/// `[[package]]` starts an entry, named by its `name = "..."` line.
+fn cargo_lock_line(text: &str) -> LockLine<'_> {
+    if text == "[[package]]" {
+        return LockLine::Entry(None);
+    }
+    if let Some(name) = quoted_value(text, "name = ") {
+        return LockLine::Entry(Some(name.to_string()));
+    }
+    quoted_value(text, "version = ").map_or(LockLine::Other, LockLine::Version)
+}
+
+/// Any object opens an entry, a package when it's `"node_modules/<name>": {`.
+fn npm_lock_line(text: &str) -> LockLine<'_> {
+    if let Some(key) = text.strip_suffix('{').map(str::trim_end) {
+        let name = key
+            .strip_suffix(':')
+            .and_then(|key| key.trim().strip_prefix('"')?.strip_suffix('"'))
+            .and_then(|key| key.rsplit_once("node_modules/"))
+            .map(|(_, name)| name.to_string());
+        return LockLine::Entry(name);
+    }
+    quoted_value(text.trim_end_matches(','), "\"version\": ")
+        .map_or(LockLine::Other, LockLine::Version)
+}
+
+/// The string after `prefix` in `prefix"value"`.
+fn quoted_value<'a>(text: &'a str, prefix: &str) -> Option<&'a str> {
+    text.strip_prefix(prefix)?
+        .strip_prefix('"')?
+        .strip_suffix('"')
+}
+
+/// Replaces the `similarity index`, `rename from` and `rename to` (or `copy`)
+/// lines git writes for a moved file with a "Renamed a -> b (90% similar)"
+/// note, so the model doesn't take the move for a rewrite. Any hunks of the
+/// file are kept.
+pub fn describe_renames(diff: &str) -> String {
+    file_sections(diff)
+        .into_iter()
+        .map(|section| match rename_note(section) {
+            Some(note) => {
+                let mut described = String::new();
+                let mut noted = false;
+                for line in section.split_inclusive('\n') {
+                    if !is_rename_header(line) {
+                        described.push_str(line);
+                    } else if !noted {
+                        described.push_str(&note);
+                        noted = true;
+                    }
+                }
+                described
+            }
+            None => section.to_string(),
+        })
+        .collect()
+}
+
+fn rename_note(section: &str) -> Option<String> {
+    let mut similarity = None;
+    let mut from = None;
+    let mut to = None;
+    let mut verb = "Renamed";
+
+    // The extended headers end where the hunks or binary notice begin
+    for line in section.lines().skip(1) {
+        if line.starts_with("--- ") || line.starts_with("@@") || line.starts_with("Binary ") {
+            break;
+        }
+        if let Some(percent) = line.strip_prefix("similarity index ") {
+            similarity = Some(percent);
+        } else if let Some(path) = line.strip_prefix("rename from ") {
+            from = Some(path);
+        } else if let Some(path) = line.strip_prefix("rename to ") {
+            to = Some(path);
+        } else if let Some(path) = line.strip_prefix("copy from ") {
+            (from, verb) = (Some(path), "Copied");
+        } else if let Some(path) = line.strip_prefix("copy to ") {
+            to = Some(path);
+        }
+    }
+
+    let (from, to) = (from?, to?);
+    Some(match similarity {
+        Some(similarity) => format!("{} {} -> {} ({} similar)\n", verb, from, to, similarity),
+        None => format!("{} {} -> {}\n", verb, from, to),
+    })
+}
+
+fn is_rename_header(line: &str) -> bool {
+    [
+        "similarity index ",
+        "rename from ",
+        "rename to ",
+        "copy from ",
+        "copy to ",
+    ]
+    .iter()
+    .any(|prefix| line.starts_with(prefix))
+}
+
+/// Replaces the `Subproject commit` hunks of submodule bumps with a readable
+/// one-line summary per submodule.
+pub fn describe_submodules(diff: &str) -> String {
+    file_sections(diff)
+        .into_iter()
+        .map(|section| match submodule_summary(section) {
+            Some(summary) => summary,
+            None => section.to_string(),
+        })
+        .collect()
+}
+
+fn submodule_summary(section: &str) -> Option<String> {
+    let path = section_path(section)?;
+    let mut old = None;
+    let mut new = None;
+
+    for line in section.lines() {
+        if let Some(sha) = line.strip_prefix("-Subproject commit ") {
+            old = Some(short_sha(sha));
+        } else if let Some(sha) = line.strip_prefix("+Subproject commit ") {
+            new = Some(short_sha(sha));
+        } else if (line.starts_with('+') || line.starts_with('-'))
+            && !line.starts_with("+++")
+            && !line.starts_with("---")
+        {
+            // Some other content changed, so this isn't a plain submodule bump
+            return None;
+        }
+    }
+
+    let summary = match (old, new) {
+        (Some(old), Some(new)) => format!("Submodule {} updated from {} to {}", path, old, new),
+        (None, Some(new)) => format!("Submodule {} added at {}", path, new),
+        (Some(old), None) => format!("Submodule {} removed (was at {})", path, old),
+        (None, None) => return None,
+    };
+    Some(summary + "\n")
+}
+
+fn short_sha(sha: &str) -> &str {
+    let sha = sha.trim().trim_end_matches("-dirty");
+    &sha[..sha.len().min(7)]
+}
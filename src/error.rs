@@ -0,0 +1,78 @@
+/// Failures that wrapping scripts may want to tell apart. Each maps to a stable
+/// process exit code; any other error exits with 1.
+#[derive(Debug, thiserror::Error)]
+pub enum PrError {
+    /// The working tree has uncommitted changes (exit code 2).
+    #[error("There are uncommitted changes. Please commit or stash them before proceeding.")]
+    UncommittedChanges,
+    /// The command was not run inside a git repository (exit code 3).
+    #[error("Not inside a git repository")]
+    NotARepo,
+    /// The model API request failed (exit code 4).
+    #[error("API error: {0}")]
+    Api(String),
+    /// A `gh` command failed (exit code 5).
+    #[error("{0}")]
+    Gh(String),
+    /// There is nothing to open a pull request for (exit code 6).
+    #[error("{0}")]
+    NoChanges(String),
+    /// The run took longer than `--timeout` (exit code 7).
+    #[error("{0}")]
+    Timeout(String),
+}
+
+impl PrError {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            PrError::UncommittedChanges => 2,
+            PrError::NotARepo => 3,
+            PrError::Api(_) => 4,
+            PrError::Gh(_) => 5,
+            PrError::NoChanges(_) => 6,
+            PrError::Timeout(_) => 7,
+        }
+    }
+}
+
+/// The exit code for a top-level error, 1 when it isn't a [`PrError`].
+pub fn exit_code(error: &(dyn std::error::Error + 'static)) -> i32 {
+    error
+        .downcast_ref::<PrError>()
+        .map_or(1, PrError::exit_code)
+}
+
+/// A [`PrError::Gh`] for a failed `gh` subcommand, carrying its stderr, or
+/// pointing at `gh auth login` when the failure is because it isn't logged in.
+pub fn gh_failure(subcommand: &str, stderr: &[u8]) -> PrError {
+    let stderr = String::from_utf8_lossy(stderr);
+    if is_auth_failure(&stderr) {
+        return PrError::Gh(format!(
+            "gh {} failed because gh is not logged in. Run `gh auth login` (with \
+             `--hostname <host>` for GitHub Enterprise) and try again.",
+            subcommand
+        ));
+    }
+
+    PrError::Gh(format!("gh {} failed: {}", subcommand, stderr.trim()))
+}
+
+/// A [`PrError::Gh`] for a `gh` command that couldn't be started.
+pub fn gh_spawn_failure(error: std::io::Error) -> PrError {
+    if error.kind() == std::io::ErrorKind::NotFound {
+        return PrError::Gh(
+            "gh is not installed or not on PATH. Install it from https://cli.github.com \
+             and run `gh auth login`."
+                .to_string(),
+        );
+    }
+
+    PrError::Gh(format!("Failed to run gh: {}", error))
+}
+
+fn is_auth_failure(stderr: &str) -> bool {
+    stderr.contains("gh auth login")
+        || stderr.contains("not logged into")
+        || stderr.contains("HTTP 401")
+        || stderr.contains("Bad credentials")
+}
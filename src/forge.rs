@@ -0,0 +1,576 @@
+use clap::ValueEnum;
+use serde::Deserialize;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use crate::error::{gh_failure, gh_spawn_failure, PrError};
+use crate::git::{get_named_remote_url, get_remote_url};
+use crate::reviewers::without_excluded;
+
+/// The title used when none could be generated.
+pub const PR_TITLE: &str = "Automated Pull Request";
+const GITHUB_HOST: &str = "github.com";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ForgeKind {
+    Github,
+    Gitlab,
+}
+
+#[derive(Debug, Clone)]
+pub struct Forge {
+    pub kind: ForgeKind,
+    pub host: String,
+    /// The repository `gh` runs in.
+    pub dir: PathBuf,
+}
+
+impl Forge {
+    pub fn ensure_supported(&self) -> Result<(), Box<dyn std::error::Error>> {
+        match self.kind {
+            ForgeKind::Github => Ok(()),
+            ForgeKind::Gitlab => {
+                Err(format!("{} is a GitLab host, which is not supported yet", self.host).into())
+            }
+        }
+    }
+
+    /// Checks that `gh` is logged in to this host.
+    pub fn check_auth(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let output = self
+            .gh()
+            .args(["auth", "status", "--hostname", &self.host])
+            .output()
+            .map_err(gh_spawn_failure)?;
+        if !output.status.success() {
+            return Err(gh_failure("auth status", &output.stderr).into());
+        }
+
+        Ok(())
+    }
+
+    /// The login of the user `gh` is authenticated as.
+    pub fn current_user(&self) -> Result<String, Box<dyn std::error::Error>> {
+        let output = self
+            .gh()
+            .args(["api", "user", "--jq", ".login"])
+            .output()
+            .map_err(gh_spawn_failure)?;
+        if !output.status.success() {
+            return Err(gh_failure("api user", &output.stderr).into());
+        }
+
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    }
+
+    /// A `gh` command pointed at this forge's host.
+    pub fn gh(&self) -> Command {
+        let mut command = Command::new("gh");
+        command.current_dir(&self.dir);
+        if self.host != GITHUB_HOST {
+            command.env("GH_HOST", &self.host);
+        }
+        command
+    }
+}
+
+/// Extracts the host from an SSH (`git@host:owner/repo.git`, `ssh://git@host/...`)
+/// or HTTPS (`https://host/owner/repo.git`) remote URL.
+pub fn remote_host(url: &str) -> Option<String> {
+    split_remote(url).map(|(host, _)| host)
+}
+
+/// The lowercased host and the repository path of a remote URL.
+fn split_remote(url: &str) -> Option<(String, &str)> {
+    let (host, path) = match url.split_once("://") {
+        Some((_, rest)) => {
+            let (authority, path) = rest.split_once('/').unwrap_or((rest, ""));
+            let authority = authority.rsplit('@').next()?;
+            (authority.split(':').next()?, path)
+        }
+        None => {
+            let (authority, path) = url.split_once(':')?;
+            (authority.rsplit('@').next()?, path)
+        }
+    };
+
+    if host.is_empty() {
+        None
+    } else {
+        Some((host.to_lowercase(), path))
+    }
+}
+
+/// Where the repository lives on its forge.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RepoInfo {
+    pub host: String,
+    /// The user or organization, including any GitLab subgroups.
+    pub owner: String,
+    pub name: String,
+}
+
+impl RepoInfo {
+    /// The `owner/name` form used by `gh --repo` and the REST API.
+    pub fn slug(&self) -> String {
+        format!("{}/{}", self.owner, self.name)
+    }
+}
+
+/// Parses an SSH or HTTPS remote URL like [`remote_host`], also taking the
+/// owner and repository name from its path.
+pub fn parse_remote_url(url: &str) -> Option<RepoInfo> {
+    let (host, path) = split_remote(url)?;
+    let path = path.trim_matches('/');
+    let path = path.strip_suffix(".git").unwrap_or(path);
+    let (owner, name) = path.rsplit_once('/')?;
+    if owner.is_empty() || name.is_empty() {
+        return None;
+    }
+
+    Some(RepoInfo {
+        host,
+        owner: owner.to_string(),
+        name: name.to_string(),
+    })
+}
+
+/// The forge location of the `origin` remote.
+pub fn repo_info(repo: &Path) -> Result<RepoInfo, Box<dyn std::error::Error>> {
+    let url = get_remote_url(repo)?;
+    parse_remote_url(&url).ok_or_else(|| {
+        format!(
+            "Could not tell the owner and repository from remote {}, \
+             expected a URL like git@github.com:owner/repo.git",
+            url
+        )
+        .into()
+    })
+}
+
+/// Where a pull request from a fork is opened: the upstream repository, and
+/// the `owner:branch` head in the fork.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ForkTarget {
+    pub repo: String,
+    pub head: String,
+}
+
+/// Works out a cross-fork pull request. `upstream` is the repository the pull
+/// request goes into, `origin` when there is no separate upstream remote, and
+/// the head owner is `head_owner` or else the owner of `origin`. Returns
+/// `None` when head and base are the same repository.
+pub fn cross_fork(
+    origin: &RepoInfo,
+    upstream: Option<&RepoInfo>,
+    head_owner: Option<&str>,
+    branch: &str,
+) -> Option<ForkTarget> {
+    let base = upstream.unwrap_or(origin);
+    let head_owner = head_owner.unwrap_or(&origin.owner);
+    if head_owner == base.owner {
+        return None;
+    }
+
+    Some(ForkTarget {
+        repo: base.slug(),
+        head: format!("{}:{}", head_owner, branch),
+    })
+}
+
+/// [`cross_fork`] for this repository, taking the upstream from a remote
+/// named `upstream`.
+pub fn fork_target(
+    repo: &Path,
+    head_owner: Option<&str>,
+    branch: &str,
+) -> Result<Option<ForkTarget>, Box<dyn std::error::Error>> {
+    let upstream = match get_named_remote_url(repo, "upstream")? {
+        Some(url) => Some(parse_remote_url(&url).ok_or_else(|| {
+            format!(
+                "Could not tell the owner and repository from remote {}",
+                url
+            )
+        })?),
+        None => None,
+    };
+    if upstream.is_none() && head_owner.is_none() {
+        return Ok(None);
+    }
+
+    Ok(cross_fork(
+        &repo_info(repo)?,
+        upstream.as_ref(),
+        head_owner,
+        branch,
+    ))
+}
+
+pub fn resolve_forge(
+    repo: &Path,
+    kind: Option<ForgeKind>,
+    host: Option<String>,
+) -> Result<Forge, Box<dyn std::error::Error>> {
+    let host = match host {
+        Some(host) => host,
+        None => {
+            let url = get_remote_url(repo)?;
+            remote_host(&url)
+                .ok_or_else(|| format!("Could not determine the forge host from remote {}", url))?
+        }
+    };
+
+    let kind = kind.unwrap_or(if host.contains("gitlab") {
+        ForgeKind::Gitlab
+    } else {
+        ForgeKind::Github
+    });
+
+    Ok(Forge {
+        kind,
+        host,
+        dir: repo.to_path_buf(),
+    })
+}
+
+/// An open pull request for the current branch.
+#[derive(Debug, Clone)]
+pub struct ExistingPr {
+    pub url: String,
+    pub is_draft: bool,
+}
+
+/// Looks up the pull request for `head`, or for the current branch when
+/// `None`, if there is one.
+pub fn find_pull_request(
+    forge: &Forge,
+    head: Option<&str>,
+) -> Result<Option<ExistingPr>, Box<dyn std::error::Error>> {
+    let output = forge
+        .gh()
+        .args(["pr", "view"])
+        .args(head)
+        .args(["--json", "url,isDraft,state"])
+        .output()
+        .map_err(gh_spawn_failure)?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if stderr.contains("no pull requests found") {
+            return Ok(None);
+        }
+        return Err(gh_failure("pr view", &output.stderr).into());
+    }
+
+    let pr: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+    if pr["state"] != "OPEN" {
+        return Ok(None);
+    }
+
+    Ok(Some(ExistingPr {
+        url: pr["url"].as_str().unwrap_or_default().to_string(),
+        is_draft: pr["isDraft"].as_bool().unwrap_or_default(),
+    }))
+}
+
+/// Bodies of the most recently merged pull requests, newest first, skipping
+/// empty ones.
+pub fn recent_merged_bodies(
+    forge: &Forge,
+    limit: usize,
+) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let output = forge
+        .gh()
+        .args([
+            "pr", "list", "--state", "merged", "--json", "body", "--limit",
+        ])
+        .arg(limit.to_string())
+        .output()
+        .map_err(gh_spawn_failure)?;
+    if !output.status.success() {
+        return Err(gh_failure("pr list", &output.stderr).into());
+    }
+
+    let prs: Vec<serde_json::Value> = serde_json::from_slice(&output.stdout)?;
+    Ok(prs
+        .iter()
+        .filter_map(|pr| pr["body"].as_str())
+        .map(str::trim)
+        .filter(|body| !body.is_empty())
+        .map(str::to_string)
+        .collect())
+}
+
+pub fn update_pull_request(
+    forge: &Forge,
+    pr_url: &str,
+    description: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let output = forge
+        .gh()
+        .args(["pr", "edit", pr_url, "--body", description])
+        .output()
+        .map_err(gh_spawn_failure)?;
+
+    if !output.status.success() {
+        return Err(gh_failure("pr edit", &output.stderr).into());
+    }
+
+    Ok(())
+}
+
+pub fn mark_ready(forge: &Forge, pr_url: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let output = forge
+        .gh()
+        .args(["pr", "ready", pr_url])
+        .output()
+        .map_err(gh_spawn_failure)?;
+
+    if !output.status.success() {
+        return Err(gh_failure("pr ready", &output.stderr).into());
+    }
+
+    Ok(())
+}
+
+/// Comments on a GitHub issue with a link to the pull request addressing it.
+/// `repo` is the `owner/name` the issue is in, when it isn't the current
+/// repository's.
+pub fn comment_on_issue(
+    forge: &Forge,
+    repo: Option<&str>,
+    issue: u64,
+    pr_url: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut command = forge.gh();
+    command.args(["issue", "comment", &issue.to_string(), "--body"]);
+    command.arg(format!("Addressed in {}", pr_url));
+    if let Some(repo) = repo {
+        command.args(["--repo", repo]);
+    }
+    let output = command.output().map_err(gh_spawn_failure)?;
+
+    if !output.status.success() {
+        return Err(gh_failure("issue comment", &output.stderr).into());
+    }
+
+    Ok(())
+}
+
+/// How the pull request is meant to be merged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum MergeStrategy {
+    Squash,
+    Rebase,
+    Merge,
+}
+
+impl MergeStrategy {
+    fn flag(self) -> &'static str {
+        match self {
+            MergeStrategy::Squash => "--squash",
+            MergeStrategy::Rebase => "--rebase",
+            MergeStrategy::Merge => "--merge",
+        }
+    }
+
+    /// The line noting the merge method in the body.
+    pub fn body_note(self) -> String {
+        let method = match self {
+            MergeStrategy::Squash => "squash",
+            MergeStrategy::Rebase => "rebase",
+            MergeStrategy::Merge => "merge commit",
+        };
+        format!("\n\nMerge method: {}", method)
+    }
+}
+
+/// Turns on auto-merge, so the pull request lands with `strategy` once its
+/// required reviews and checks pass. gh refuses when the repository doesn't
+/// allow auto-merge.
+pub fn enable_auto_merge(
+    forge: &Forge,
+    pr_url: &str,
+    strategy: MergeStrategy,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let output = forge
+        .gh()
+        .args(["pr", "merge", pr_url, "--auto", strategy.flag()])
+        .output()
+        .map_err(gh_spawn_failure)?;
+
+    if !output.status.success() {
+        return Err(gh_failure("pr merge", &output.stderr).into());
+    }
+
+    Ok(())
+}
+
+/// An inline comment on one line of the new side of a pull request's diff.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct ReviewComment {
+    pub path: String,
+    pub line: u32,
+    pub body: String,
+}
+
+/// The `owner/repo` slug and number of a pull request from its URL, e.g.
+/// `https://github.com/owner/repo/pull/12`.
+pub fn parse_pr_url(url: &str) -> Option<(String, u64)> {
+    let path = url.split_once("://")?.1.split_once('/')?.1;
+    let (slug, number) = path.trim_end_matches('/').rsplit_once("/pull/")?;
+    if slug.split('/').count() != 2 {
+        return None;
+    }
+    Some((slug.to_string(), number.parse().ok()?))
+}
+
+/// Leaves `comments` on the pull request as a single review that only
+/// comments, neither approving nor requesting changes.
+pub fn post_review_comments(
+    forge: &Forge,
+    pr_url: &str,
+    comments: &[ReviewComment],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let (slug, number) =
+        parse_pr_url(pr_url).ok_or_else(|| format!("Unrecognized pull request URL {}", pr_url))?;
+    let comments: Vec<serde_json::Value> = comments
+        .iter()
+        .map(|comment| {
+            serde_json::json!({
+                "path": comment.path,
+                "line": comment.line,
+                "side": "RIGHT",
+                "body": comment.body,
+            })
+        })
+        .collect();
+    let review = serde_json::json!({ "event": "COMMENT", "comments": comments });
+
+    let mut child = forge
+        .gh()
+        .args(["api", "--method", "POST", "--input", "-"])
+        .arg(format!("repos/{}/pulls/{}/reviews", slug, number))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(gh_spawn_failure)?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(review.to_string().as_bytes())?;
+    }
+    let output = child.wait_with_output()?;
+
+    if !output.status.success() {
+        return Err(gh_failure("api pulls reviews", &output.stderr).into());
+    }
+
+    Ok(())
+}
+
+/// Extra fields set on a new pull request.
+#[derive(Debug, Clone, Default)]
+pub struct PrMetadata {
+    /// Logins to request reviews from.
+    pub reviewers: Vec<String>,
+    /// Logins never requested for review, such as bots. The author is always
+    /// left out as well.
+    pub excluded_reviewers: Vec<String>,
+    /// GitHub Projects, by title, to add the pull request to.
+    pub projects: Vec<String>,
+    /// Set when the branch lives in a fork of the repository the pull request is for.
+    pub fork: Option<ForkTarget>,
+    /// The branch to open the pull request from when `HEAD` isn't on it, as
+    /// in the detached checkout of a CI run.
+    pub head: Option<String>,
+    /// Open the pull request as a draft.
+    pub draft: bool,
+}
+
+/// A pull request opened by [`create_pull_request`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CreatedPr {
+    pub number: u64,
+    pub url: String,
+}
+
+impl CreatedPr {
+    /// Reads the pull request from the output of `gh pr create`, which prints
+    /// its URL as the last line.
+    pub fn from_gh_output(stdout: &str) -> Option<CreatedPr> {
+        let url = stdout
+            .lines()
+            .rev()
+            .map(str::trim)
+            .find(|line| !line.is_empty())?;
+        let (_, number) = parse_pr_url(url)?;
+        Some(CreatedPr {
+            number,
+            url: url.to_string(),
+        })
+    }
+}
+
+pub async fn create_pull_request(
+    forge: &Forge,
+    base: &str,
+    title: &str,
+    description: &str,
+    metadata: &PrMetadata,
+) -> Result<CreatedPr, Box<dyn std::error::Error>> {
+    let mut command = forge.gh();
+    command.args([
+        "pr",
+        "create",
+        "--title",
+        title,
+        "--body",
+        description,
+        "--base",
+        base,
+    ]);
+    let mut reviewers = without_excluded(&metadata.reviewers, &metadata.excluded_reviewers);
+    if !reviewers.is_empty() {
+        if let Ok(author) = forge.current_user() {
+            reviewers = without_excluded(&reviewers, &[author]);
+        }
+    }
+    if !reviewers.is_empty() {
+        command.args(["--reviewer", &reviewers.join(",")]);
+    }
+    for project in &metadata.projects {
+        command.args(["--project", project]);
+    }
+    if let Some(fork) = &metadata.fork {
+        command.args(["--repo", &fork.repo, "--head", &fork.head]);
+    } else if let Some(head) = &metadata.head {
+        command.args(["--head", head]);
+    }
+    if metadata.draft {
+        command.arg("--draft");
+    }
+    let output = command.output().map_err(gh_spawn_failure)?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if stderr.contains("No commits between") {
+            return Err(PrError::NoChanges(
+                "There are no commits between the base branch and this branch. \
+                 Push your commits or check that you are targeting the right base."
+                    .to_string(),
+            )
+            .into());
+        }
+        return Err(gh_failure("pr create", &output.stderr).into());
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    CreatedPr::from_gh_output(&stdout).ok_or_else(|| {
+        format!(
+            "gh pr create succeeded but didn't print a pull request URL: {}",
+            stdout.trim()
+        )
+        .into()
+    })
+}
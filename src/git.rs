@@ -0,0 +1,866 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use clap::ValueEnum;
+
+use crate::diff::normalize_text;
+use crate::error::PrError;
+use crate::retry::{retry, Backoff};
+
+pub const DEFAULT_BASE: &str = "master";
+
+const PUSH_BACKOFF: Backoff = Backoff {
+    attempts: 3,
+    initial: Duration::from_secs(2),
+    max: Duration::from_secs(8),
+    jitter: true,
+};
+
+/// A `git` command running inside `repo`.
+fn git(repo: &Path) -> Command {
+    let mut command = Command::new("git");
+    command.current_dir(repo);
+    command
+}
+
+pub fn check_uncommitted_changes(repo: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let output = git(repo).args(["status", "--porcelain"]).output()?;
+
+    if !output.status.success() {
+        return Err(PrError::NotARepo.into());
+    }
+    if !output.stdout.is_empty() {
+        return Err(PrError::UncommittedChanges.into());
+    }
+
+    Ok(())
+}
+
+/// Repositories whose changes a [`StashGuard`] holds, so they can be put
+/// back by [`restore_stashes`] on an exit that skips the guards' drop.
+static ACTIVE_STASHES: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+/// Local changes stashed for the length of a run, put back when dropped so
+/// they come back on failure too.
+#[derive(Debug)]
+pub struct StashGuard {
+    repo: PathBuf,
+}
+
+impl Drop for StashGuard {
+    fn drop(&mut self) {
+        let mut active = ACTIVE_STASHES.lock().unwrap();
+        // Already put back by restore_stashes
+        let Some(index) = active.iter().position(|repo| *repo == self.repo) else {
+            return;
+        };
+        active.remove(index);
+        drop(active);
+        pop_stash(&self.repo);
+    }
+}
+
+/// Pops the changes of every [`StashGuard`] that hasn't put them back yet,
+/// for exits that don't unwind, like the `--timeout` watchdog.
+pub fn restore_stashes() {
+    let repos = std::mem::take(&mut *ACTIVE_STASHES.lock().unwrap());
+    for repo in repos {
+        pop_stash(&repo);
+    }
+}
+
+fn pop_stash(repo: &Path) {
+    let popped = git(repo)
+        .args(["stash", "pop", "--quiet"])
+        .status()
+        .is_ok_and(|status| status.success());
+    if !popped {
+        eprintln!(
+            "Warning: could not restore your stashed changes, run `git stash pop` to get them back"
+        );
+    }
+}
+
+/// Stashes uncommitted changes, untracked files included, returning `None`
+/// when there was nothing to stash.
+pub fn stash_changes(repo: &Path) -> Result<Option<StashGuard>, Box<dyn std::error::Error>> {
+    let status = git(repo).args(["status", "--porcelain"]).output()?;
+    if !status.status.success() {
+        return Err(PrError::NotARepo.into());
+    }
+    if status.stdout.is_empty() {
+        return Ok(None);
+    }
+
+    let output = git(repo)
+        .args([
+            "stash",
+            "push",
+            "--quiet",
+            "--include-untracked",
+            "-m",
+            "pullrequest --auto-stash",
+        ])
+        .output()?;
+    if !output.status.success() {
+        return Err(format!(
+            "git stash failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+
+    ACTIVE_STASHES.lock().unwrap().push(repo.to_path_buf());
+    Ok(Some(StashGuard {
+        repo: repo.to_path_buf(),
+    }))
+}
+
+pub fn push_to_remote(repo: &Path, current_branch: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let pushed = retry(
+        &PUSH_BACKOFF,
+        |stderr: &String, _| is_transient_push_failure(stderr),
+        |_| {
+            let output = git(repo)
+                .args(["push", "origin", current_branch])
+                .output()
+                .map_err(|e| e.to_string())?;
+            if output.status.success() {
+                Ok(())
+            } else {
+                Err(String::from_utf8_lossy(&output.stderr).into_owned())
+            }
+        },
+    );
+
+    match pushed {
+        Ok(()) => Ok(()),
+        Err(stderr) if is_non_fast_forward(&stderr) => Err(format!(
+            "Push to origin/{} was rejected because the remote has commits this branch \
+             doesn't. Pull and rebase first, or push with `git push --force-with-lease` \
+             if you rewrote history on purpose.",
+            current_branch
+        )
+        .into()),
+        Err(stderr) => Err(format!("Failed to push to remote: {}", stderr.trim()).into()),
+    }
+}
+
+fn is_non_fast_forward(stderr: &str) -> bool {
+    stderr.contains("non-fast-forward") || stderr.contains("(fetch first)")
+}
+
+/// Network failures worth another attempt, as opposed to rejections and
+/// authentication errors that will fail the same way again.
+fn is_transient_push_failure(stderr: &str) -> bool {
+    [
+        "Could not resolve host",
+        "Connection timed out",
+        "Connection reset",
+        "Connection refused",
+        "Operation timed out",
+        "Failed to connect",
+        "The remote end hung up unexpectedly",
+        "early EOF",
+    ]
+    .iter()
+    .any(|marker| stderr.contains(marker))
+}
+
+pub fn check_for_remote(repo: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    // Get the current branch name
+    let current_branch = get_current_branch(repo)?;
+
+    // Check if the branch has a remote
+    if !has_remote(repo, "origin", &current_branch)? {
+        // If no remote, push to origin
+        push_to_remote(repo, &current_branch)?;
+    }
+
+    Ok(())
+}
+
+pub fn get_current_branch(repo: &Path) -> Result<String, Box<dyn std::error::Error>> {
+    let output = git(repo)
+        .args(["rev-parse", "--abbrev-ref", "HEAD"])
+        .output()?;
+
+    if output.status.success() {
+        Ok(String::from_utf8(output.stdout)?.trim().to_string())
+    } else {
+        Err("Failed to get current branch".into())
+    }
+}
+
+pub fn get_head_sha(repo: &Path) -> Result<String, Box<dyn std::error::Error>> {
+    let output = git(repo).args(["rev-parse", "HEAD"]).output()?;
+
+    if output.status.success() {
+        Ok(String::from_utf8(output.stdout)?.trim().to_string())
+    } else {
+        Err("Failed to resolve HEAD".into())
+    }
+}
+
+/// How the local branch tip compares to the same branch on `origin`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RemoteState {
+    InSync,
+    /// The branch hasn't been pushed at all.
+    Missing,
+    /// Local and remote commits the other side doesn't have.
+    Diverged {
+        ahead: usize,
+        behind: usize,
+    },
+}
+
+/// Fetches `branch` from `origin` and compares it with `HEAD`, for runs that
+/// rely on the branch already being pushed.
+pub fn remote_state(repo: &Path, branch: &str) -> Result<RemoteState, Box<dyn std::error::Error>> {
+    if !has_remote(repo, "origin", branch)? {
+        return Ok(RemoteState::Missing);
+    }
+
+    let fetch = git(repo).args(["fetch", "-q", "origin", branch]).output()?;
+    if !fetch.status.success() {
+        return Err(format!(
+            "Failed to fetch origin/{}: {}",
+            branch,
+            String::from_utf8_lossy(&fetch.stderr).trim()
+        )
+        .into());
+    }
+
+    let output = git(repo)
+        .args(["rev-list", "--left-right", "--count"])
+        .arg(format!("HEAD...refs/remotes/origin/{}", branch))
+        .output()?;
+    let counts = String::from_utf8_lossy(&output.stdout);
+    let mut counts = counts.split_whitespace().map(str::parse::<usize>);
+    match (counts.next(), counts.next()) {
+        (Some(Ok(0)), Some(Ok(0))) => Ok(RemoteState::InSync),
+        (Some(Ok(ahead)), Some(Ok(behind))) => Ok(RemoteState::Diverged { ahead, behind }),
+        _ => Err(format!("Failed to compare HEAD with origin/{}", branch).into()),
+    }
+}
+
+/// Whether `branch` exists on `remote`, asking the remote itself.
+pub fn has_remote(
+    repo: &Path,
+    remote: &str,
+    branch: &str,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    let output = git(repo)
+        .args(["ls-remote", "--exit-code", "--heads", remote, branch])
+        .output()?;
+
+    Ok(output.status.success())
+}
+
+pub fn get_repo_root(repo: &Path) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let output = git(repo).args(["rev-parse", "--show-toplevel"]).output()?;
+
+    if output.status.success() {
+        Ok(PathBuf::from(String::from_utf8(output.stdout)?.trim()))
+    } else {
+        Err(PrError::NotARepo.into())
+    }
+}
+
+pub fn get_remote_url(repo: &Path) -> Result<String, Box<dyn std::error::Error>> {
+    let output = git(repo).args(["remote", "get-url", "origin"]).output()?;
+
+    if output.status.success() {
+        Ok(String::from_utf8(output.stdout)?.trim().to_string())
+    } else {
+        Err("Failed to get the origin remote URL".into())
+    }
+}
+
+/// The URL of a remote other than `origin`, or `None` when it doesn't exist.
+pub fn get_named_remote_url(
+    repo: &Path,
+    remote: &str,
+) -> Result<Option<String>, Box<dyn std::error::Error>> {
+    let output = git(repo).args(["remote", "get-url", remote]).output()?;
+
+    if output.status.success() {
+        Ok(Some(String::from_utf8(output.stdout)?.trim().to_string()))
+    } else {
+        Ok(None)
+    }
+}
+
+/// The remote's default branch (what `origin/HEAD` points at). When that isn't
+/// known locally, [`DEFAULT_BASE`] or `main`, whichever is on origin, with
+/// [`DEFAULT_BASE`] as the last resort.
+pub fn get_default_branch(repo: &Path) -> Result<String, Box<dyn std::error::Error>> {
+    let output = git(repo)
+        .args([
+            "symbolic-ref",
+            "--quiet",
+            "--short",
+            "refs/remotes/origin/HEAD",
+        ])
+        .output()?;
+
+    let head = String::from_utf8(output.stdout)?;
+    match head.trim().strip_prefix("origin/") {
+        Some(branch) if output.status.success() && !branch.is_empty() => Ok(branch.to_string()),
+        _ => {
+            for branch in [DEFAULT_BASE, "main"] {
+                if remote_branch_exists(repo, "origin", branch)? {
+                    return Ok(branch.to_string());
+                }
+            }
+            Ok(DEFAULT_BASE.to_string())
+        }
+    }
+}
+
+/// The branch HEAD tracks, without its remote prefix.
+pub fn get_upstream_branch(repo: &Path) -> Result<Option<String>, Box<dyn std::error::Error>> {
+    let output = git(repo)
+        .args(["rev-parse", "--abbrev-ref", "@{upstream}"])
+        .output()?;
+
+    if !output.status.success() {
+        return Ok(None);
+    }
+
+    let upstream = String::from_utf8(output.stdout)?;
+    Ok(upstream
+        .trim()
+        .split_once('/')
+        .map(|(_, branch)| branch.to_string()))
+}
+
+/// The bases a pull request from this branch could reasonably target, the
+/// repository default first. More than one means guessing may pick the wrong
+/// one: both `main` and `master` exist, or the branch tracks something other
+/// than the default.
+pub fn base_candidates(repo: &Path) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let mut candidates = vec![get_default_branch(repo)?];
+    for branch in ["main", "master"] {
+        if remote_branch_exists(repo, "origin", branch)? {
+            candidates.push(branch.to_string());
+        }
+    }
+    let current = get_current_branch(repo)?;
+    if let Some(upstream) = get_upstream_branch(repo)? {
+        if upstream != current {
+            candidates.push(upstream);
+        }
+    }
+
+    let mut seen = Vec::new();
+    candidates.retain(|branch| {
+        let new = !seen.contains(branch);
+        seen.push(branch.clone());
+        new
+    });
+    Ok(candidates)
+}
+
+/// Whether `<remote>/<branch>` is known locally.
+pub fn remote_branch_exists(
+    repo: &Path,
+    remote: &str,
+    branch: &str,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    let status = git(repo)
+        .args(["rev-parse", "--verify", "--quiet"])
+        .arg(format!("refs/remotes/{}/{}", remote, branch))
+        .stdout(std::process::Stdio::null())
+        .status()?;
+
+    Ok(status.success())
+}
+
+/// Resolves the `--base` flag. `auto` picks the upstream tracking branch for
+/// stacked branches and otherwise, like no flag at all, the default branch.
+pub fn resolve_base(repo: &Path, base: Option<&str>) -> Result<String, Box<dyn std::error::Error>> {
+    match base {
+        Some("auto") => {
+            let current = get_current_branch(repo)?;
+            match get_upstream_branch(repo)? {
+                // A branch pushed with `-u` tracks itself, which is no use as a base
+                Some(upstream) if upstream != current => Ok(upstream),
+                _ => get_default_branch(repo),
+            }
+        }
+        Some(base) => Ok(base.to_string()),
+        None => get_default_branch(repo),
+    }
+}
+
+/// The most recent tag reachable from `HEAD`.
+pub fn latest_tag(repo: &Path) -> Result<String, Box<dyn std::error::Error>> {
+    let output = git(repo)
+        .args(["describe", "--tags", "--abbrev=0"])
+        .output()?;
+    if !output.status.success() {
+        return Err("No tags found before HEAD, tag the last release or pass --range".into());
+    }
+
+    Ok(String::from_utf8(output.stdout)?.trim().to_string())
+}
+
+/// The range covering the commits on HEAD that are not on `base` of `remote`.
+pub fn base_range(remote: &str, base: &str) -> String {
+    format!("{}/{}..HEAD", remote, base)
+}
+
+/// The remote pull requests go into: `upstream` when there is one, as in a
+/// fork, and otherwise `origin`.
+pub fn base_remote(repo: &Path) -> Result<&'static str, Box<dyn std::error::Error>> {
+    Ok(match get_named_remote_url(repo, "upstream")? {
+        Some(_) => "upstream",
+        None => "origin",
+    })
+}
+
+/// Checks that `range` has the form `A..B` and that both ends resolve to commits.
+pub fn validate_range(repo: &Path, range: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let (from, to) = range
+        .split_once("..")
+        .filter(|(from, to)| !from.is_empty() && !to.is_empty() && !to.starts_with('.'))
+        .ok_or_else(|| format!("Invalid range `{}`, expected <A>..<B>", range))?;
+
+    for end in [from, to] {
+        let status = git(repo)
+            .args(["rev-parse", "--verify", "--quiet"])
+            .arg(format!("{}^{{commit}}", end))
+            .stdout(std::process::Stdio::null())
+            .status()?;
+        if !status.success() {
+            return Err(format!("`{}` in range `{}` is not a valid revision", end, range).into());
+        }
+    }
+
+    Ok(())
+}
+
+/// How many commits HEAD has that `<remote>/<base>` doesn't.
+pub fn commits_ahead(
+    repo: &Path,
+    remote: &str,
+    base: &str,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    let output = git(repo)
+        .args(["rev-list", "--count", &base_range(remote, base)])
+        .output()?;
+
+    if !output.status.success() {
+        return Err(format!(
+            "Failed to count the commits ahead of {}/{}: {}",
+            remote,
+            base,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+    Ok(String::from_utf8(output.stdout)?.trim().parse()?)
+}
+
+/// Lines added and removed in one file, `None` for binary files.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileStat {
+    pub path: String,
+    pub added: Option<u64>,
+    pub removed: Option<u64>,
+}
+
+pub fn get_file_stats(repo: &Path, range: &str) -> Result<Vec<FileStat>, std::io::Error> {
+    let output = git(repo).args(["diff", "--numstat", range]).output()?;
+
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.splitn(3, '\t');
+            let added = fields.next()?.parse().ok();
+            let removed = fields.next()?.parse().ok();
+            Some(FileStat {
+                path: fields.next()?.to_string(),
+                added,
+                removed,
+            })
+        })
+        .collect())
+}
+
+/// The staged changes, or with `amend` everything the amended last commit
+/// will contain.
+pub fn get_staged_diff(repo: &Path, amend: bool) -> Result<String, std::io::Error> {
+    let mut diff = git(repo);
+    diff.args(["diff", "--cached"]);
+    if amend {
+        diff.arg("HEAD^");
+    }
+    let output = diff.output()?;
+
+    Ok(normalize_text(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// Commits the staged changes with `message`, amending the last commit when
+/// `amend` is set.
+pub fn commit(repo: &Path, message: &str, amend: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let mut commit = git(repo);
+    commit.args(["commit", "-q", "-m", message]);
+    if amend {
+        commit.arg("--amend");
+    }
+    let output = commit.output()?;
+    if !output.status.success() {
+        return Err(format!(
+            "git commit failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+
+    Ok(())
+}
+
+/// Lines of unchanged context around each change, git's own default.
+pub const DEFAULT_CONTEXT_LINES: u32 = 3;
+
+/// How similar, in percent, a deleted and an added file must be for git to
+/// call them a rename or copy, git's own default.
+pub const DEFAULT_RENAME_THRESHOLD: u8 = 50;
+
+/// The algorithm git uses to line up the old and new versions of a file. Some
+/// code gets much cleaner hunks from `patience` or `histogram` than from the
+/// default `myers`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum DiffAlgorithm {
+    Myers,
+    Minimal,
+    Patience,
+    Histogram,
+}
+
+impl DiffAlgorithm {
+    fn flag(self) -> &'static str {
+        match self {
+            DiffAlgorithm::Myers => "--diff-algorithm=myers",
+            DiffAlgorithm::Minimal => "--diff-algorithm=minimal",
+            DiffAlgorithm::Patience => "--diff-algorithm=patience",
+            DiffAlgorithm::Histogram => "--diff-algorithm=histogram",
+        }
+    }
+}
+
+/// The diff of `range` with `context_lines` lines of context around each
+/// change, computed with `algorithm`, or git's configured default when `None`.
+/// Files at least `rename_threshold` percent similar to a deleted or existing
+/// one show up as renames or copies instead of as new files.
+pub fn get_git_diff(
+    repo: &Path,
+    range: &str,
+    context_lines: u32,
+    algorithm: Option<DiffAlgorithm>,
+    rename_threshold: u8,
+) -> Result<String, std::io::Error> {
+    let output = git(repo)
+        .arg("diff")
+        .arg(format!("-U{}", context_lines))
+        .args(algorithm.map(DiffAlgorithm::flag))
+        .arg(format!("-M{}%", rename_threshold))
+        .arg(format!("-C{}%", rename_threshold))
+        .arg(range)
+        .output()?;
+
+    Ok(normalize_text(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// Subjects of the commits in `range`. With `cherry`, commits whose changes
+/// already exist on the left side of the range (e.g. cherry-picked or rebased
+/// onto base) are left out.
+pub fn get_commit_messages(
+    repo: &Path,
+    range: &str,
+    cherry: bool,
+) -> Result<Vec<String>, std::io::Error> {
+    let output = log(repo, range, cherry)
+        .arg("--pretty=format:%s")
+        .output()?;
+
+    let messages = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(String::from)
+        .collect();
+
+    Ok(messages)
+}
+
+/// The distinct authors of the commits in `range`, oldest first, including
+/// anyone credited with a `Co-authored-by` trailer.
+pub fn get_commit_authors(
+    repo: &Path,
+    range: &str,
+    cherry: bool,
+) -> Result<Vec<String>, std::io::Error> {
+    let output = log(repo, range, cherry)
+        .args([
+            "--reverse",
+            "--pretty=format:%an%n%(trailers:key=Co-authored-by,valueonly,separator=%x0A)",
+        ])
+        .output()?;
+
+    let mut authors: Vec<String> = Vec::new();
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        // Trailers are `Name <email>`, only the name is wanted
+        let name = line.split('<').next().unwrap_or_default().trim();
+        if !name.is_empty() && !authors.iter().any(|author| author == name) {
+            authors.push(name.to_string());
+        }
+    }
+
+    Ok(authors)
+}
+
+/// The subjects of one author's commits.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuthorCommits {
+    pub author: String,
+    pub subjects: Vec<String>,
+}
+
+/// The non-merge commits in `range` grouped by author, oldest first, with the
+/// authors in the order of their first commit.
+pub fn get_commits_by_author(
+    repo: &Path,
+    range: &str,
+    cherry: bool,
+) -> Result<Vec<AuthorCommits>, std::io::Error> {
+    let output = log(repo, range, cherry)
+        .args(["--no-merges", "--reverse", "--pretty=format:%an%x00%s"])
+        .output()?;
+
+    let mut groups: Vec<AuthorCommits> = Vec::new();
+    for (author, subject) in String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| line.split_once('\0'))
+    {
+        match groups.iter_mut().find(|group| group.author == author) {
+            Some(group) => group.subjects.push(subject.to_string()),
+            None => groups.push(AuthorCommits {
+                author: author.to_string(),
+                subjects: vec![subject.to_string()],
+            }),
+        }
+    }
+
+    Ok(groups)
+}
+
+/// One commit of the range with the changes it makes on its own.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommitPatch {
+    pub subject: String,
+    pub diff: String,
+}
+
+/// The commits in `range`, oldest first, each with its own diff.
+pub fn get_commit_patches(
+    repo: &Path,
+    range: &str,
+    cherry: bool,
+    context_lines: u32,
+    algorithm: Option<DiffAlgorithm>,
+) -> Result<Vec<CommitPatch>, std::io::Error> {
+    let output = log(repo, range, cherry)
+        .args(["--reverse", "--pretty=format:%H %s"])
+        .output()?;
+
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| line.split_once(' '))
+        .map(|(sha, subject)| {
+            let show = git(repo)
+                .args(["show", "--format="])
+                .arg(format!("-U{}", context_lines))
+                .args(algorithm.map(DiffAlgorithm::flag))
+                .arg(sha)
+                .output()?;
+            Ok(CommitPatch {
+                subject: subject.to_string(),
+                diff: normalize_text(&String::from_utf8_lossy(&show.stdout)),
+            })
+        })
+        .collect()
+}
+
+/// A commit the branch backs out.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Revert {
+    /// The subject of the reverted commit.
+    pub subject: String,
+    /// The reverted commit, when known.
+    pub sha: Option<String>,
+}
+
+/// How many commits of base [`find_inverted_commit`] looks back through.
+const INVERSE_SEARCH_DEPTH: usize = 200;
+
+/// The commits reverted by those in `range`, going by `git revert`'s
+/// `Revert "<subject>"` subject and "This reverts commit <sha>." body.
+pub fn get_reverts(repo: &Path, range: &str, cherry: bool) -> Result<Vec<Revert>, std::io::Error> {
+    let output = log(repo, range, cherry)
+        .args(["--reverse", "--format=%s%x00%b%x1e"])
+        .output()?;
+
+    let reverts = String::from_utf8_lossy(&output.stdout)
+        .split('\x1e')
+        .filter_map(|commit| {
+            let (subject, body) = commit.trim_start().split_once('\0')?;
+            let subject = subject.strip_prefix("Revert \"")?.strip_suffix('"')?;
+            let sha = body.split_once("This reverts commit ").map(|(_, rest)| {
+                rest.chars()
+                    .take_while(char::is_ascii_hexdigit)
+                    .collect::<String>()
+            });
+            Some(Revert {
+                subject: subject.to_string(),
+                sha: sha.filter(|sha| !sha.is_empty()),
+            })
+        })
+        .collect();
+
+    Ok(reverts)
+}
+
+/// The recent commit on `base`, a ref such as `origin/main`, whose changes
+/// the whole of `range` exactly undoes, for reverts that weren't made with
+/// `git revert`. Patches are compared by `git patch-id`, so line numbers and
+/// context may differ.
+pub fn find_inverted_commit(
+    repo: &Path,
+    range: &str,
+    base: &str,
+) -> Result<Option<Revert>, Box<dyn std::error::Error>> {
+    // -R swaps the a/ and b/ prefixes too, which patch ids include
+    let inverse = git(repo)
+        .args(["diff", "-R", "--src-prefix=b/", "--dst-prefix=a/"])
+        .arg(range)
+        .output()?;
+    if !inverse.status.success() {
+        return Err(format!(
+            "git diff failed: {}",
+            String::from_utf8_lossy(&inverse.stderr).trim()
+        )
+        .into());
+    }
+    let Some((inverse_id, _)) = patch_ids(repo, &inverse.stdout)?.into_iter().next() else {
+        return Ok(None);
+    };
+
+    let history = git(repo)
+        .args(["log", "-p", "--no-merges"])
+        .arg(format!("-{}", INVERSE_SEARCH_DEPTH))
+        .arg(base)
+        .output()?;
+    if !history.status.success() {
+        return Err(format!(
+            "git log failed: {}",
+            String::from_utf8_lossy(&history.stderr).trim()
+        )
+        .into());
+    }
+    let Some((_, sha)) = patch_ids(repo, &history.stdout)?
+        .into_iter()
+        .find(|(id, _)| *id == inverse_id)
+    else {
+        return Ok(None);
+    };
+
+    let subject = git(repo)
+        .args(["log", "-1", "--format=%s"])
+        .arg(&sha)
+        .output()?;
+    Ok(Some(Revert {
+        subject: String::from_utf8_lossy(&subject.stdout).trim().to_string(),
+        sha: Some(sha),
+    }))
+}
+
+/// `(patch id, commit)` pairs for the patches in `patches`, as printed by
+/// `git patch-id --stable`.
+fn patch_ids(repo: &Path, patches: &[u8]) -> Result<Vec<(String, String)>, std::io::Error> {
+    let mut child = git(repo)
+        .args(["patch-id", "--stable"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()?;
+    child
+        .stdin
+        .take()
+        .expect("stdin is piped")
+        .write_all(patches)?;
+    let output = child.wait_with_output()?;
+
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| line.split_once(' '))
+        .map(|(id, sha)| (id.to_string(), sha.to_string()))
+        .collect())
+}
+
+/// Whether a Conventional Commits subject has `scope`, as in `feat(api): ...`
+/// or `fix(api)!: ...`.
+pub fn has_scope(subject: &str, scope: &str) -> bool {
+    let Some((prefix, _)) = subject.split_once(':') else {
+        return false;
+    };
+    let prefix = prefix.strip_suffix('!').unwrap_or(prefix);
+    prefix
+        .split_once('(')
+        .and_then(|(_, rest)| rest.strip_suffix(')'))
+        .is_some_and(|found| found.trim() == scope)
+}
+
+/// Words that mark a branch or commit as unfinished, unless configured otherwise.
+pub const DEFAULT_WIP_PATTERNS: [&str; 2] = ["wip", "draft"];
+
+/// Whether the branch looks like unfinished work: one of `patterns` is a word
+/// of the branch name, as in `wip/login` or `login-draft`, or the first word
+/// of the latest commit subject, as in `WIP: login` or `[draft] login`.
+/// Matching ignores case.
+pub fn is_work_in_progress(
+    branch: &str,
+    latest_subject: Option<&str>,
+    patterns: &[String],
+) -> bool {
+    let matches = |word: &str| {
+        patterns
+            .iter()
+            .any(|pattern| !word.is_empty() && word.eq_ignore_ascii_case(pattern))
+    };
+    let first_word = latest_subject
+        .and_then(|subject| subject.split_whitespace().next())
+        .map(|word| word.trim_matches(|c: char| !c.is_alphanumeric()));
+
+    branch.split(|c: char| !c.is_alphanumeric()).any(matches) || first_word.is_some_and(matches)
+}
+
+/// `git log` over the commits of `range`, leaving out ones already applied on
+/// the other side when `cherry` is set.
+fn log(repo: &Path, range: &str, cherry: bool) -> Command {
+    let mut log = git(repo);
+    log.arg("log");
+    if cherry {
+        let symmetric = match range.split_once("..") {
+            Some((from, to)) if !to.starts_with('.') => format!("{}...{}", from, to),
+            _ => range.to_string(),
+        };
+        log.args(["--cherry-pick", "--right-only", &symmetric]);
+    } else {
+        log.arg(range);
+    }
+    log
+}
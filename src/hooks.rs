@@ -0,0 +1,31 @@
+use std::path::Path;
+use std::process::Command;
+
+/// Runs a user-supplied shell command in `repo` with `envs` exported, returning
+/// its combined output. A non-zero exit is an error carrying that output.
+pub fn run_hook(
+    repo: &Path,
+    command: &str,
+    envs: &[(&str, &str)],
+) -> Result<String, Box<dyn std::error::Error>> {
+    let output = Command::new("sh")
+        .args(["-c", command])
+        .current_dir(repo)
+        .envs(envs.iter().copied())
+        .output()?;
+
+    let mut combined = String::from_utf8_lossy(&output.stdout).to_string();
+    combined.push_str(&String::from_utf8_lossy(&output.stderr));
+
+    if !output.status.success() {
+        return Err(format!(
+            "Hook `{}` failed ({}):\n{}",
+            command,
+            output.status,
+            combined.trim_end()
+        )
+        .into());
+    }
+
+    Ok(combined)
+}
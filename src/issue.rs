@@ -0,0 +1,252 @@
+use std::fmt;
+
+/// An issue referenced by the branch name or commit messages.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IssueRef {
+    /// A GitHub issue the pull request closes.
+    GitHub(u64),
+    /// A GitHub issue or pull request only mentioned in passing, like the
+    /// `(#45)` of a squash-merged subject, which must not be closed.
+    GitHubMention(u64),
+    Jira(String),
+}
+
+impl fmt::Display for IssueRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IssueRef::GitHub(number) | IssueRef::GitHubMention(number) => {
+                write!(f, "#{}", number)
+            }
+            IssueRef::Jira(key) => f.write_str(key),
+        }
+    }
+}
+
+/// Finds the issues a branch refers to.
+///
+/// GitHub issues are `#123` anywhere, or a number segment in the branch name
+/// such as `123-fix-login` or `issue-123`. In commit subjects only a `#123`
+/// after a closing keyword (`Fixes #123`) is one the pull request closes, any
+/// other is a [`IssueRef::GitHubMention`]. Jira keys (`PROJ-1234`) are taken
+/// from the branch name and from the start of commit subjects, where teams put
+/// them by convention; elsewhere in prose they are too easily confused with
+/// things like `SHA-256`.
+pub fn detect_issues(branch: &str, commit_messages: &[String]) -> Vec<IssueRef> {
+    let mut issues = Vec::new();
+    let mut add = |issue: IssueRef| {
+        // A number that is closed anywhere is more than a mention
+        match issue {
+            IssueRef::GitHub(number) => {
+                issues.retain(|found| *found != IssueRef::GitHubMention(number))
+            }
+            IssueRef::GitHubMention(number) if issues.contains(&IssueRef::GitHub(number)) => return,
+            _ => {}
+        }
+        if !issues.contains(&issue) {
+            issues.push(issue);
+        }
+    };
+
+    for key in jira_keys(branch) {
+        add(IssueRef::Jira(key));
+    }
+    for number in branch_issue_numbers(branch) {
+        add(IssueRef::GitHub(number));
+    }
+    for message in commit_messages {
+        let subject = message.trim_start_matches(['[', '(']);
+        if let Some(key) = jira_keys(subject).into_iter().next() {
+            if subject.starts_with(&key) {
+                add(IssueRef::Jira(key));
+            }
+        }
+        for (i, number) in hash_reference_positions(message) {
+            if follows_closing_keyword(&message[..i]) {
+                add(IssueRef::GitHub(number));
+            } else {
+                add(IssueRef::GitHubMention(number));
+            }
+        }
+    }
+
+    issues
+}
+
+/// `#123` references in free text.
+fn hash_references(text: &str) -> Vec<u64> {
+    hash_reference_positions(text)
+        .into_iter()
+        .map(|(_, number)| number)
+        .collect()
+}
+
+/// `#123` references in free text, with the index of their `#`.
+fn hash_reference_positions(text: &str) -> Vec<(usize, u64)> {
+    text.match_indices('#')
+        .filter(|(i, _)| {
+            // Skip things like `foo#1` and markdown headings
+            *i == 0 || !text[..*i].ends_with(|c: char| c.is_alphanumeric())
+        })
+        .filter_map(|(i, _)| leading_number(&text[i + 1..]).map(|number| (i, number)))
+        .collect()
+}
+
+/// Whether `before` ends with one of GitHub's closing keywords, as in
+/// `Fixes #12` or `resolves: #12`.
+fn follows_closing_keyword(before: &str) -> bool {
+    const KEYWORDS: [&str; 9] = [
+        "close", "closes", "closed", "fix", "fixes", "fixed", "resolve", "resolves", "resolved",
+    ];
+    let word = before
+        .trim_end()
+        .trim_end_matches(':')
+        .rsplit(|c: char| !c.is_alphanumeric())
+        .next()
+        .unwrap_or_default();
+    KEYWORDS
+        .iter()
+        .any(|keyword| word.eq_ignore_ascii_case(keyword))
+}
+
+fn branch_issue_numbers(branch: &str) -> Vec<u64> {
+    let mut numbers = hash_references(branch);
+    let name = branch.rsplit('/').next().unwrap_or(branch);
+    let mut parts = name.split(['-', '_']);
+
+    let first = parts.next().unwrap_or_default();
+    let number = match first.to_lowercase().as_str() {
+        "issue" | "issues" | "gh" | "fix" | "bug" => parts.next().and_then(leading_number),
+        _ => first.parse().ok(),
+    };
+    numbers.extend(number.filter(|n| *n > 0));
+
+    // `issues/123` style prefixes
+    if let Some((prefix, rest)) = branch.split_once('/') {
+        if matches!(prefix, "issue" | "issues") {
+            numbers.extend(leading_number(rest));
+        }
+    }
+
+    numbers
+}
+
+fn leading_number(text: &str) -> Option<u64> {
+    let digits: String = text.chars().take_while(char::is_ascii_digit).collect();
+    let rest = &text[digits.len()..];
+    if rest.starts_with(|c: char| c.is_alphabetic()) {
+        return None;
+    }
+    digits.parse().ok()
+}
+
+/// Jira-style keys: an uppercase project (letters, then letters or digits)
+/// followed by `-` and a number, delimited as a whole word.
+fn jira_keys(text: &str) -> Vec<String> {
+    let bytes = text.as_bytes();
+    let mut keys = Vec::new();
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let at_word_start = i == 0 || !bytes[i - 1].is_ascii_alphanumeric();
+        if !at_word_start || !bytes[i].is_ascii_uppercase() {
+            i += 1;
+            continue;
+        }
+
+        let mut j = i;
+        while j < bytes.len() && (bytes[j].is_ascii_uppercase() || bytes[j].is_ascii_digit()) {
+            j += 1;
+        }
+        let project_len = j - i;
+        if project_len >= 2 && j < bytes.len() && bytes[j] == b'-' {
+            let mut k = j + 1;
+            while k < bytes.len() && bytes[k].is_ascii_digit() {
+                k += 1;
+            }
+            let at_word_end = k == bytes.len() || !bytes[k].is_ascii_alphanumeric();
+            if k > j + 1 && at_word_end {
+                keys.push(text[i..k].to_string());
+                i = k;
+                continue;
+            }
+        }
+        i = j.max(i + 1);
+    }
+
+    keys
+}
+
+/// Where detected Jira keys link to, and optionally how to look up their summaries.
+#[derive(Debug, Clone)]
+pub struct JiraConfig {
+    pub base_url: String,
+    /// API token; with an email it is sent as basic auth (Jira Cloud), otherwise
+    /// as a bearer token (Jira Data Center personal access tokens).
+    pub token: Option<String>,
+    pub email: Option<String>,
+}
+
+impl JiraConfig {
+    pub fn issue_url(&self, key: &str) -> String {
+        format!("{}/browse/{}", self.base_url.trim_end_matches('/'), key)
+    }
+
+    pub async fn fetch_summary(&self, key: &str) -> Result<String, Box<dyn std::error::Error>> {
+        let token = self.token.as_deref().ok_or("no Jira token configured")?;
+        let url = format!(
+            "{}/rest/api/2/issue/{}?fields=summary",
+            self.base_url.trim_end_matches('/'),
+            key
+        );
+
+        let request = reqwest::Client::new().get(url);
+        let request = match &self.email {
+            Some(email) => request.basic_auth(email, Some(token)),
+            None => request.bearer_auth(token),
+        };
+        let issue: serde_json::Value = request.send().await?.error_for_status()?.json().await?;
+
+        issue["fields"]["summary"]
+            .as_str()
+            .map(String::from)
+            .ok_or_else(|| format!("Jira issue {} has no summary", key).into())
+    }
+}
+
+/// The section appended to the body for the linked issues: `Closes #N` for
+/// GitHub issues, `Refs #N` for ones only mentioned, and links (with summaries
+/// where known) for Jira keys when a Jira base URL is configured.
+pub fn issue_links_block(
+    issues: &[IssueRef],
+    jira: Option<&JiraConfig>,
+    jira_summaries: &[(String, String)],
+) -> String {
+    let mut lines = Vec::new();
+
+    for issue in issues {
+        match (issue, jira) {
+            (IssueRef::GitHub(number), _) => lines.push(format!("Closes #{}", number)),
+            (IssueRef::GitHubMention(number), _) => lines.push(format!("Refs #{}", number)),
+            (IssueRef::Jira(key), Some(jira)) => {
+                let summary = jira_summaries
+                    .iter()
+                    .find(|(k, _)| k == key)
+                    .map(|(_, summary)| format!(": {}", summary))
+                    .unwrap_or_default();
+                lines.push(format!(
+                    "Jira: [{}]({}){}",
+                    key,
+                    jira.issue_url(key),
+                    summary
+                ));
+            }
+            (IssueRef::Jira(_), None) => {}
+        }
+    }
+
+    if lines.is_empty() {
+        String::new()
+    } else {
+        format!("\n\n{}", lines.join("\n"))
+    }
+}
@@ -0,0 +1,18 @@
+pub mod ai;
+pub mod body;
+pub mod ci;
+pub mod config;
+pub mod credentials;
+pub mod diff;
+pub mod error;
+pub mod forge;
+pub mod git;
+pub mod hooks;
+pub mod issue;
+pub mod notify;
+pub mod plan;
+pub mod progress;
+pub mod resume;
+pub mod retry;
+pub mod reviewers;
+pub mod template;
@@ -1,181 +1,1853 @@
-use anthropic::{client::ClientBuilder, types::CompleteRequestBuilder, AI_PROMPT, HUMAN_PROMPT};
+use clap::{Parser, Subcommand};
 use dotenv::dotenv;
-use std::os::unix::process::CommandExt;
-use std::process::Command;
+use pullrequest::ai::{
+    build_commit_message_prompt, build_explain_prompt, build_polish_prompt, build_prompt,
+    build_self_review_prompt, build_title_prompt, clean_title, commit_sections,
+    generate_pr_description, parse_api_base, parse_review_comments, parse_structured,
+    summarize_diff, Audience, ModelConfig, PromptOptions, DEFAULT_MODEL,
+};
+use pullrequest::body::{
+    branch_title, commit_subjects_body, commits_by_author_block, dependency_updates_block,
+    deterministic_body, diff_details_block, file_table_block, mentioned_teams, mentions_block,
+    sanitize_body, strip_ansi, tidy_markdown, truncate_body, wrap_body, DEFAULT_BODY_MAX_CHARS,
+};
+use pullrequest::ci::GithubActions;
+use pullrequest::config::{load_ignore_patterns, starter_config, write_starter_files, Config};
+use pullrequest::credentials::{resolve_api_key, ApiKey, KeySource};
+use pullrequest::diff::{
+    changed_line_count, commentable_lines, dependency_updates, describe_renames,
+    describe_submodules, diff_chunks, exclude_files, file_sections, omit_long_lines,
+    redact_secrets, removed_public_items, section_path, test_paths, truncate_diff,
+    truncate_diff_evenly, truncated_files, LargeDiff, TruncateStrategy, DEFAULT_MAX_DIFF_LINES,
+    DEFAULT_MAX_LINE_LENGTH, LOCKFILES,
+};
+use pullrequest::error::{exit_code, PrError};
+use pullrequest::forge::{
+    comment_on_issue, create_pull_request, enable_auto_merge, find_pull_request, fork_target,
+    mark_ready, post_review_comments, recent_merged_bodies, repo_info, resolve_forge,
+    update_pull_request, Forge, ForgeKind, MergeStrategy, PrMetadata, ReviewComment, PR_TITLE,
+};
+use pullrequest::git::{
+    base_candidates, base_range, base_remote, check_for_remote, check_uncommitted_changes, commit,
+    commits_ahead, find_inverted_commit, get_commit_authors, get_commit_messages,
+    get_commit_patches, get_commits_by_author, get_current_branch, get_default_branch,
+    get_file_stats, get_git_diff, get_head_sha, get_repo_root, get_reverts, get_staged_diff,
+    has_remote, has_scope, is_work_in_progress, latest_tag, push_to_remote, remote_branch_exists,
+    remote_state, resolve_base, restore_stashes, stash_changes, validate_range, DiffAlgorithm,
+    RemoteState, DEFAULT_CONTEXT_LINES, DEFAULT_RENAME_THRESHOLD, DEFAULT_WIP_PATTERNS,
+};
+use pullrequest::hooks::run_hook;
+use pullrequest::issue::{detect_issues, issue_links_block, IssueRef, JiraConfig};
+use pullrequest::notify::notify_slack;
+use pullrequest::plan::Plan;
+use pullrequest::progress::{
+    abandon_active_step, format_count, run_with_elapsed_async, run_with_progress,
+    run_with_progress_async, run_with_summary, set_quiet,
+};
+use pullrequest::resume::{default_state_dir, diff_hash, RunState};
+use pullrequest::reviewers::{
+    check_min_reviewers, default_rotation_path, without_excluded, Rotation,
+};
+use pullrequest::template::{default_cache_dir, fetch_template};
+use std::io::{IsTerminal, Read};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
 
-fn check_uncommitted_changes() -> Result<(), Box<dyn std::error::Error>> {
-    let output = Command::new("git")
-        .args(&["status", "--porcelain"])
-        .output()?;
+const DEFAULT_BRIEF_THRESHOLD: usize = 10;
 
-    if !output.stdout.is_empty() {
-        eprintln!("There are uncommitted changes. Please commit or stash them before proceeding.");
-        std::process::exit(1);
-    }
+/// Set by `--print-url`, which keeps stdout for the pull request URL alone.
+static PRINT_URL: AtomicBool = AtomicBool::new(false);
 
-    Ok(())
+/// `println!` for everything but the result, which goes to stderr instead
+/// with `--print-url`.
+macro_rules! status {
+    ($($arg:tt)*) => {
+        if PRINT_URL.load(Ordering::Relaxed) {
+            eprintln!($($arg)*);
+        } else {
+            println!($($arg)*);
+        }
+    };
+}
+/// How often `--watch` checks HEAD, and how long HEAD must stay put before
+/// the description is regenerated.
+const WATCH_POLL: Duration = Duration::from_secs(5);
+const WATCH_DEBOUNCE: Duration = Duration::from_secs(10);
+
+#[derive(Parser, Debug, Clone)]
+#[command(version, about)]
+struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Run in this repository instead of the current directory, like `git -C`
+    #[arg(
+        short = 'C',
+        long,
+        value_name = "PATH",
+        default_value = ".",
+        global = true
+    )]
+    repo_dir: PathBuf,
+
+    /// Hide the progress spinners, the default inside GitHub Actions
+    #[arg(long, short = 'q')]
+    quiet: bool,
+
+    /// Show the progress spinners inside GitHub Actions too
+    #[arg(long, conflicts_with = "quiet")]
+    no_quiet: bool,
+
+    /// Print only the pull request URL on stdout, everything else goes to stderr
+    #[arg(long, conflicts_with_all = ["dry_run", "body_only", "explain"])]
+    print_url: bool,
+
+    /// Slack incoming webhook to notify after the pull request is created
+    #[arg(long, env = "SLACK_WEBHOOK")]
+    slack_webhook: Option<String>,
+
+    /// Copy the generated description to the clipboard
+    #[arg(long)]
+    copy: bool,
+
+    /// Only print the generated body: no pushing and no gh at all
+    #[arg(long, conflicts_with_all = ["dry_run", "amend", "ready", "round_robin", "style_examples"])]
+    body_only: bool,
+
+    /// Print the description and check that the pull request could be created, without pushing or creating it
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Model used to generate the title and description [default: claude-3-haiku-20240307]
+    #[arg(long)]
+    model: Option<String>,
+
+    /// Model that writes the description, instead of --model
+    #[arg(long, value_name = "MODEL")]
+    body_model: Option<String>,
+
+    /// Model that writes the title, instead of --model; a cheaper one usually does
+    #[arg(long, value_name = "MODEL")]
+    title_model: Option<String>,
+
+    /// Model to fall back to when the previous one is overloaded (repeatable)
+    #[arg(long = "model-fallback", value_name = "MODEL")]
+    model_fallbacks: Vec<String>,
+
+    /// Retry when the model returns a description shorter than this many characters [default: 10]
+    #[arg(long, value_name = "CHARS", alias = "fail-on-empty-description")]
+    min_description_chars: Option<usize>,
+
+    /// Send model requests to this Anthropic-compatible endpoint, e.g. an internal gateway
+    #[arg(long, env = "ANTHROPIC_BASE_URL", value_parser = parse_api_base)]
+    api_base_url: Option<String>,
+
+    /// Forge hosting the repository, detected from the origin remote by default
+    #[arg(long, value_enum)]
+    forge: Option<ForgeKind>,
+
+    /// Forge host, e.g. a GitHub Enterprise domain, detected from the origin remote by default
+    #[arg(long)]
+    host: Option<String>,
+
+    /// Append the diff to the description inside a collapsed block
+    #[arg(long)]
+    include_diff_in_body: bool,
+
+    /// Stash uncommitted changes for the run instead of refusing to start
+    #[arg(long, conflicts_with = "skip_dirty_check")]
+    auto_stash: bool,
+
+    /// Go ahead with uncommitted changes in the tree, leaving them untouched
+    #[arg(long, global = true)]
+    skip_dirty_check: bool,
+
+    /// Don't push; check that the branch on origin matches HEAD instead
+    #[arg(long, alias = "assume-pushed")]
+    no_push: bool,
+
+    /// With --no-push, fail instead of warning when origin doesn't match HEAD
+    #[arg(long, requires = "no_push")]
+    strict: bool,
+
+    /// Request reviews from the next people in the `team` rotation from .pullrequest.toml
+    #[arg(long)]
+    round_robin: bool,
+
+    /// Never request reviews from these logins, e.g. bots (comma separated)
+    #[arg(long, value_name = "LOGINS", value_delimiter = ',')]
+    reviewers_exclude: Vec<String>,
+
+    /// Refuse to create the pull request with fewer reviewers than this
+    #[arg(long, value_name = "N")]
+    min_reviewers: Option<usize>,
+
+    /// Add the pull request to this GitHub project, by title (repeatable)
+    #[arg(long = "project", value_name = "TITLE")]
+    projects: Vec<String>,
+
+    /// Add a table of changed files with their added and removed line counts to the body
+    #[arg(long)]
+    file_table: bool,
+
+    /// Most files to list in the --file-table table
+    #[arg(long, value_name = "ROWS", default_value_t = 25)]
+    file_table_rows: usize,
+
+    /// Update the description of the branch's existing pull request instead of creating one
+    #[arg(long)]
+    amend: bool,
+
+    /// Update the existing pull request and mark it ready for review if it is a draft
+    #[arg(long)]
+    ready: bool,
+
+    /// Open the pull request as a draft
+    #[arg(long, overrides_with = "no_draft")]
+    draft: bool,
+
+    /// Don't open the pull request as a draft, even when the branch looks like work in progress
+    #[arg(long, overrides_with = "draft")]
+    no_draft: bool,
+
+    /// Shell command to run before the description is generated; a failure aborts
+    #[arg(long, value_name = "COMMAND")]
+    pre_hook: Option<String>,
+
+    /// Shell command to run after the pull request is created, with PR_URL, PR_NUMBER and PR_TITLE set
+    #[arg(long, value_name = "COMMAND")]
+    post_hook: Option<String>,
+
+    /// Describe this commit range (e.g. v1.0..v1.1) instead of the branch against its base
+    #[arg(long, value_name = "A..B")]
+    range: Option<String>,
+
+    /// Describe everything since the most recent tag as release notes
+    #[arg(long, conflicts_with = "range")]
+    since_last_tag: bool,
+
+    /// Hard-wrap the description to this many columns
+    #[arg(long = "wrap", value_name = "COLUMNS", value_parser = clap::value_parser!(u16).range(20..))]
+    wrap_width: Option<u16>,
+
+    /// Send raw `Subproject commit` lines instead of summarizing submodule bumps
+    #[arg(long)]
+    raw_submodules: bool,
+
+    /// Send lines that look like secrets, and changes to .env*, *.pem, *.key and id_rsa files, instead of redacting them
+    #[arg(long)]
+    include_env_files: bool,
+
+    /// Extra instruction appended to the end of the prompt
+    #[arg(long, value_name = "TEXT")]
+    prompt_append: Option<String>,
+
+    /// Branch to open the pull request against; `auto` follows the upstream tracking branch
+    #[arg(long, global = true)]
+    base: Option<String>,
+
+    /// Leave out commits already on the other side of the range (on by default without --range)
+    #[arg(long, overrides_with = "no_cherry")]
+    cherry: bool,
+
+    /// Include commits even if an equivalent one is already on the base
+    #[arg(long, overrides_with = "cherry")]
+    no_cherry: bool,
+
+    /// Jira instance that detected issue keys (e.g. PROJ-1234) link to
+    #[arg(long, env = "JIRA_BASE_URL", value_name = "URL")]
+    jira_base_url: Option<String>,
+
+    /// Jira API token, used to look up the summaries of linked Jira issues
+    #[arg(long, env = "JIRA_TOKEN", hide_env_values = true)]
+    jira_token: Option<String>,
+
+    /// Account email for Jira Cloud; the token is sent as basic auth when set
+    #[arg(long, env = "JIRA_EMAIL")]
+    jira_email: Option<String>,
+
+    /// Print the assembled prompt to stderr; with --dry-run, stop before calling the API
+    #[arg(long)]
+    print_prompt: bool,
+
+    /// Tailor the description's tone, length and sections to its readers
+    #[arg(long, value_enum)]
+    audience: Option<Audience>,
+
+    /// List at most this many of the most recent commits in the prompt
+    #[arg(long, value_name = "N")]
+    max_commits: Option<usize>,
+
+    /// Anthropic API key, used instead of ANTHROPIC_KEY; other users can see it in the process list, so prefer --api-key-file
+    #[arg(long, value_name = "KEY")]
+    api_key: Option<ApiKey>,
+
+    /// File whose contents are the Anthropic API key, e.g. a mounted CI secret
+    #[arg(long, value_name = "PATH", conflicts_with = "api_key")]
+    api_key_file: Option<PathBuf>,
+
+    /// Shell command whose output is the Anthropic API key, e.g. `pass show anthropic`
+    #[arg(long, value_name = "COMMAND")]
+    key_command: Option<String>,
+
+    /// Read the Anthropic API key from the OS keychain (service `pullrequest`, account `anthropic`)
+    #[arg(long)]
+    keychain: bool,
+
+    /// Don't detect or link issues
+    #[arg(long, global = true)]
+    no_issue: bool,
+
+    /// Fail before generating anything if no linked issue is found
+    #[arg(long, conflicts_with = "no_issue", global = true)]
+    require_issue: bool,
+
+    /// Describe the pull request from the commit messages alone
+    #[arg(long)]
+    no_diff: bool,
+
+    /// Describe the pull request from the diff alone
+    #[arg(long, conflicts_with = "no_diff")]
+    no_commit_messages: bool,
+
+    /// Add a "## Dependency Updates" table of changed Cargo.lock and package-lock.json versions, leaving the lockfiles out of the prompt
+    #[arg(long)]
+    summarize_dependencies: bool,
+
+    /// Build the description from the commits, diff stats, file table and issues without calling the model
+    #[arg(long, conflicts_with_all = ["polish", "structured", "self_review", "brief"])]
+    no_ai: bool,
+
+    /// Print an explanation of what the changes do for a reviewer, without creating a pull request
+    #[arg(long, conflicts_with_all = ["body_only", "dry_run", "watch", "round_robin", "no_ai", "structured", "polish"])]
+    explain: bool,
+
+    /// Reuse the title and description generated by the last run if it failed before the pull
+    /// request was created, as long as the diff and base are the same
+    #[arg(long, conflicts_with_all = ["explain", "no_ai"])]
+    resume: bool,
+
+    /// Describe only the staged changes instead of the branch; implies --no-commit-messages and --skip-dirty-check
+    #[arg(long, conflicts_with_all = ["no_diff", "scope", "by_commit", "auto_stash"])]
+    staged: bool,
+
+    /// Fetch the prompt instructions from this URL, cached for offline use
+    #[arg(long, alias = "template-from-url", value_name = "URL")]
+    template_url: Option<String>,
+
+    /// Replace diff lines longer than this, e.g. minified files, with a marker; 0 keeps them [default: 500]
+    #[arg(long, value_name = "CHARS")]
+    max_line_length: Option<usize>,
+
+    /// Cut the final body to this many characters, below the forge's limit [default: 60000]
+    #[arg(long, value_name = "CHARS")]
+    body_max_chars: Option<usize>,
+
+    /// Lines of unchanged context around each change in the diff [default: 3]
+    #[arg(long, value_name = "N")]
+    context_lines: Option<u32>,
+
+    /// Don't tell the model when the branch reverts earlier commits
+    #[arg(long)]
+    no_revert_detection: bool,
+
+    /// The algorithm git diffs with, git's default when unset
+    #[arg(long, value_enum, value_name = "ALGORITHM")]
+    diff_algorithm: Option<DiffAlgorithm>,
+
+    /// How similar, in percent, a moved or copied file must stay to be described as one [default: 50]
+    #[arg(long, value_name = "PERCENT", value_parser = clap::value_parser!(u8).range(1..=100))]
+    rename_threshold: Option<u8>,
+
+    /// Diffs longer than this many lines are truncated or summarized [default: 8000]
+    #[arg(long, value_name = "LINES", global = true)]
+    max_diff_lines: Option<usize>,
+
+    /// How to handle a diff over --max-diff-lines when not asking [default: truncate]
+    #[arg(long, value_name = "MODE")]
+    large_diff: Option<LargeDiff>,
+
+    /// Which files a truncated diff cuts short [default: tail]
+    #[arg(long, value_name = "STRATEGY")]
+    truncate_strategy: Option<TruncateStrategy>,
+
+    /// Don't ask questions, take the default answers instead
+    #[arg(long, short = 'y', global = true)]
+    yes: bool,
+
+    /// Add a test plan section based on the test and CI files the diff touches
+    #[arg(long)]
+    test_plan: bool,
+
+    /// Polish a description read from stdin instead of writing one from scratch
+    #[arg(long, alias = "stdin-body")]
+    polish: bool,
+
+    /// Add a breaking changes section, checked against removed `pub` items in the diff
+    #[arg(long)]
+    breaking_changes: bool,
+
+    /// Show the model the last N merged pull requests as style examples [default: 3]
+    #[arg(
+        long,
+        alias = "include-recent-pr-context",
+        value_name = "N",
+        num_args = 0..=1,
+        default_missing_value = "3"
+    )]
+    style_examples: Option<usize>,
+
+    /// Have the model reply in JSON and render it into fixed markdown sections
+    #[arg(long, conflicts_with_all = ["brief", "by_commit", "polish"])]
+    structured: bool,
+
+    /// Fill the {{summary}}, {{changes}}, {{impact}} and {{test_plan}} markers of this file with
+    /// the model's sections and use it as the body, keeping the rest of it as written
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["structured", "brief", "by_commit", "polish", "no_ai"])]
+    body_skeleton: Option<PathBuf>,
+
+    /// Only describe the Conventional Commits with this scope, e.g. `api` for feat(api): ...
+    #[arg(long, value_name = "NAME")]
+    scope: Option<String>,
+
+    /// Describe each commit in its own section, using the diff of each commit
+    #[arg(long, alias = "split-by-commit")]
+    by_commit: bool,
+
+    /// List the commit messages in the prompt grouped by author, for branches with several contributors
+    #[arg(long, conflicts_with = "staged")]
+    by_author: bool,
+
+    /// Add a "## Commits by Author" section to the body
+    #[arg(long, conflicts_with = "staged")]
+    by_author_section: bool,
+
+    /// Owner of the fork the branch was pushed to, for a pull request into upstream
+    #[arg(long, value_name = "OWNER")]
+    head_repo: Option<String>,
+
+    /// Keep running and update the pull request whenever new commits land on the branch
+    #[arg(long, conflicts_with_all = ["dry_run", "body_only", "polish", "timeout"])]
+    watch: bool,
+
+    /// Leave the final body's markdown as generated: no heading spacing or blank line cleanup
+    #[arg(long)]
+    no_tidy: bool,
+
+    /// Teams to mention with a "cc" line in the body, e.g. @org/team (comma separated)
+    #[arg(long, value_name = "TEAMS", value_delimiter = ',')]
+    mention: Vec<String>,
+
+    /// Note the intended merge method in the body
+    #[arg(long, value_name = "STRATEGY")]
+    merge: Option<MergeStrategy>,
+
+    /// Enable auto-merge with the --merge strategy once the pull request is created
+    #[arg(long, requires = "merge")]
+    auto_merge: bool,
+
+    /// Experimental: leave inline self-review comments on the most notable changes
+    #[arg(long)]
+    self_review: bool,
+
+    /// Comment on each linked GitHub issue with a link to the new pull request
+    #[arg(long)]
+    comment_on_issue: bool,
+
+    /// Leave files matching this pattern out of the diff, in addition to .pullrequestignore (repeatable)
+    #[arg(long, value_name = "PATTERN", global = true)]
+    exclude: Vec<String>,
+
+    /// List the files excluded, redacted or truncated before the diff was sent
+    #[arg(long)]
+    show_filtered: bool,
+
+    /// Leave ANSI escape sequences in the diff instead of stripping them
+    #[arg(long)]
+    keep_ansi: bool,
+
+    /// Give up and exit with code 7 if the whole run takes longer than this
+    #[arg(long, value_name = "SECONDS")]
+    timeout: Option<u64>,
+
+    /// Ask for a one or two sentence description
+    #[arg(long)]
+    brief: bool,
+
+    /// With --brief, use the commit subjects as the body when fewer lines than this changed [default: 10]
+    #[arg(long, value_name = "LINES")]
+    brief_threshold: Option<usize>,
 }
 
-fn push_to_remote(current_branch: &str) -> Result<(), Box<dyn std::error::Error>> {
-    let status = Command::new("git")
-        .args(&["push", "origin", current_branch])
-        .status()?;
+#[derive(Subcommand, Debug, Clone)]
+enum Command {
+    /// Write a conventional commit message for the staged changes and commit with it
+    CommitMsg {
+        /// Rewrite the message of the last commit instead, including any staged changes
+        #[arg(long)]
+        amend: bool,
+    },
+    /// Write a starter .pullrequest.toml and .pullrequestignore for this repository,
+    /// with --base as the base branch or the one detected from origin
+    Init {
+        /// Model to put in the config [default: claude-3-haiku-20240307]
+        #[arg(long)]
+        model: Option<String>,
+        /// Overwrite the files if they exist
+        #[arg(long)]
+        force: bool,
+    },
+    /// Check that the branch is ready for a pull request, failing with the
+    /// reasons when it isn't. Nothing is pushed, written or sent to the model
+    Check,
+}
 
-    if !status.success() {
-        eprintln!("Failed to push to remote. Please ensure your branch is up to date with origin.");
-        std::process::exit(1);
+#[tokio::main]
+async fn main() {
+    dotenv().ok();
+    let args = Args::parse();
+    // Settings next to the repository apply too, without overriding the above
+    dotenv::from_path(args.repo_dir.join(".env")).ok();
+    if let Some(timeout) = args.timeout {
+        start_watchdog(Duration::from_secs(timeout));
     }
 
-    Ok(())
+    let watch = args.watch.then(|| args.clone());
+    if let Err(e) = run(args).await {
+        eprintln!("Error: {}", e);
+        std::process::exit(exit_code(e.as_ref()));
+    }
+
+    if let Some(args) = watch {
+        if let Err(e) = watch_branch(args).await {
+            eprintln!("Error: {}", e);
+            std::process::exit(exit_code(e.as_ref()));
+        }
+    }
 }
 
-fn check_for_remote() -> Result<(), Box<dyn std::error::Error>> {
-    // Get the current branch name
-    let current_branch = get_current_branch()?;
+/// Regenerates the description of the branch's pull request whenever HEAD
+/// moves, until interrupted. Commits in quick succession, such as a rebase,
+/// are batched into one update.
+async fn watch_branch(mut args: Args) -> Result<(), Box<dyn std::error::Error>> {
+    args.amend = true;
+    let repo_dir = args.repo_dir.clone();
+    let repo = repo_dir.as_path();
+    let mut head = get_head_sha(repo)?;
+    eprintln!("Watching for new commits, press Ctrl-C to stop");
+
+    loop {
+        tokio::time::sleep(WATCH_POLL).await;
+        let mut current = get_head_sha(repo)?;
+        if current == head {
+            continue;
+        }
+        loop {
+            tokio::time::sleep(WATCH_DEBOUNCE).await;
+            let next = get_head_sha(repo)?;
+            if next == current {
+                break;
+            }
+            current = next;
+        }
+        head = current;
 
-    // Check if the branch has a remote
-    if !has_remote(&current_branch)? {
-        // If no remote, push to origin
-        push_to_remote(&current_branch)?;
+        eprintln!("HEAD moved to {}, updating the pull request", &head[..7]);
+        if let Err(e) = run(args.clone()).await {
+            eprintln!("Error: {}", e);
+        }
     }
+}
 
-    Ok(())
+/// Exits once `timeout` has passed. This runs on its own thread because most
+/// steps block on git or gh, which would keep a timer on the runtime from firing.
+fn start_watchdog(timeout: Duration) {
+    std::thread::spawn(move || {
+        std::thread::sleep(timeout);
+        let step = abandon_active_step()
+            .map(|step| format!(" during \"{}\"", step))
+            .unwrap_or_default();
+        let error = PrError::Timeout(format!("Timed out after {}s{}", timeout.as_secs(), step));
+        eprintln!("Error: {}", error);
+        // Exiting skips the drop that would put --auto-stash changes back
+        restore_stashes();
+        std::process::exit(error.exit_code());
+    });
 }
 
-fn get_current_branch() -> Result<String, Box<dyn std::error::Error>> {
-    let output = Command::new("git")
-        .args(&["rev-parse", "--abbrev-ref", "HEAD"])
-        .output()?;
+async fn run(args: Args) -> Result<(), Box<dyn std::error::Error>> {
+    // None of these modes push or write anything through gh
+    let local_only = args.dry_run || args.body_only || args.explain;
+
+    let repo_dir = args.repo_dir.clone();
+    let repo = repo_dir.as_path();
+    // Comes before loading the config, which init may be replacing
+    if let Some(Command::Init { model, force }) = &args.command {
+        return init(repo, args.base.clone(), model.clone(), *force, args.yes);
+    }
+    let config = Config::load(repo)?;
+    let actions = GithubActions::detect();
+    if let Some(Command::Check) = args.command {
+        return check(repo, &args, &config, actions.as_ref());
+    }
+    set_quiet(args.quiet || args.print_url || (actions.is_some() && !args.no_quiet));
+    PRINT_URL.store(args.print_url, Ordering::Relaxed);
+
+    let anthropic_key = if args.no_ai {
+        if matches!(args.command, Some(Command::CommitMsg { .. })) {
+            return Err("commit-msg needs the model and can't be used with --no-ai".into());
+        }
+        String::new()
+    } else {
+        resolve_api_key(&KeySource {
+            key: args.api_key,
+            key_file: args.api_key_file,
+            command: args.key_command.or(config.key_command),
+            keychain: args.keychain || config.keychain,
+        })?
+    };
+    let default_model = args
+        .model
+        .clone()
+        .or(config.model.clone())
+        .unwrap_or_else(|| DEFAULT_MODEL.to_string());
+    let models = |model: Option<String>| {
+        std::iter::once(model.unwrap_or_else(|| default_model.clone()))
+            .chain(args.model_fallbacks.iter().cloned())
+            .collect()
+    };
+    let mut model_config = ModelConfig::new(anthropic_key, models(args.body_model.clone()));
+    if let Some(api_base) = args.api_base_url {
+        model_config.api_base = api_base;
+    }
+    if let Some(min_chars) = args.min_description_chars.or(config.min_description_chars) {
+        model_config.min_chars = min_chars;
+    }
+    if let Some(human_prompt) = config.human_prompt {
+        model_config.human_prompt = human_prompt;
+    }
+    if let Some(ai_prompt) = config.ai_prompt {
+        model_config.ai_prompt = ai_prompt;
+    }
+    if let Some(stop_sequences) = config.stop_sequences {
+        model_config.stop_sequences = stop_sequences;
+    }
+
+    let title_config = ModelConfig {
+        models: models(args.title_model.clone()),
+        // Short titles like "Fix typo" are fine
+        min_chars: 1,
+        ..model_config.clone()
+    };
+
+    if let Some(Command::CommitMsg { amend }) = args.command {
+        return commit_message(repo, &model_config, amend, args.yes).await;
+    }
+
+    let forge = resolve_forge(repo, args.forge, args.host)?;
+    if !local_only {
+        forge.ensure_supported()?;
+    }
+
+    if let Some(hook) = args.pre_hook.or(config.pre_hook) {
+        let output = run_with_progress("Running pre-hook", || run_hook(repo, &hook, &[]))?;
+        if !output.trim().is_empty() {
+            eprintln!("{}", output.trim_end());
+        }
+    }
+
+    let ci_base = actions.as_ref().and_then(|ci| ci.base_ref.as_deref());
+    let pinned_base = [
+        (args.base.as_deref(), "--base"),
+        (ci_base, "GITHUB_BASE_REF"),
+        (config.base.as_deref(), "`base` in .pullrequest.toml"),
+    ]
+    .into_iter()
+    .find_map(|(base, source)| base.map(|base| (base, source)));
+    // A fork's pull requests go into upstream, so that is what the branch is
+    // compared with
+    let remote = base_remote(repo)?;
+    let base = match pinned_base {
+        Some((base, source)) => {
+            let base = resolve_base(repo, Some(base))?;
+            if !remote_branch_exists(repo, remote, &base)? {
+                return Err(format!(
+                    "Base branch {} from {} does not exist on {}. The base is taken from \
+                     --base, then GITHUB_BASE_REF in GitHub Actions, then `base` in \
+                     .pullrequest.toml, then the remote's default branch.",
+                    base, source, remote
+                )
+                .into());
+            }
+            base
+        }
+        None => choose_base(repo, args.yes)?,
+    };
+    // A pull request workflow checks out a detached merge commit, and the
+    // branch it is for is already on origin
+    let ci_head = actions.as_ref().and_then(|ci| ci.head_ref.clone());
+    let pushed_by_ci = ci_head.is_some();
+    // gh can't tell the branch from the detached checkout
+    let ci_branch = ci_head.clone();
+    let branch = match ci_head {
+        Some(branch) => branch,
+        None => get_current_branch(repo)?,
+    };
+    let range = if args.since_last_tag {
+        let tag = run_with_progress("Finding the last tag", || latest_tag(repo))?;
+        Some(format!("{}..HEAD", tag))
+    } else {
+        args.range
+    };
+    let custom_range = range.is_some();
+    let cherry = if custom_range {
+        args.cherry
+    } else {
+        !args.no_cherry
+    };
+    let range = match range {
+        Some(range) => {
+            validate_range(repo, &range)?;
+            range
+        }
+        None => base_range(remote, &base),
+    };
+
+    // Staged work can be described before anything is committed, and a
+    // --range or --since-last-tag range picks its commits regardless of base
+    let needs_commits = !((args.staged && local_only) || custom_range);
+    if needs_commits && commits_ahead(repo, remote, &base)? == 0 {
+        return Err(
+            PrError::NoChanges(format!("{} has no commits ahead of {}", branch, base)).into(),
+        );
+    }
+
+    // Held until the end of the run, popping the stash when dropped
+    let _stash = if args.auto_stash {
+        run_with_progress("Stashing local changes", || stash_changes(repo))?
+    } else {
+        None
+    };
+    if !(args.skip_dirty_check || args.staged) {
+        run_with_progress("Checking for uncommitted changes", || {
+            check_uncommitted_changes(repo)
+        })?;
+    }
+
+    let preflight_problems = if args.dry_run {
+        preflight(
+            repo,
+            &forge,
+            remote,
+            &base,
+            ci_branch.as_deref(),
+            args.amend || args.ready,
+        )
+    } else {
+        Vec::new()
+    };
+
+    let existing_pr = if (args.amend || args.ready) && !local_only {
+        let existing = run_with_progress("Looking up existing pull request", || {
+            find_pull_request(&forge, ci_branch.as_deref())
+        })?;
+        if existing.is_none() && args.amend {
+            return Err("There is no open pull request for this branch to amend".into());
+        }
+        existing
+    } else {
+        None
+    };
+
+    if args.no_push {
+        let state =
+            run_with_progress("Comparing HEAD with origin", || remote_state(repo, &branch))?;
+        let problem = match state {
+            RemoteState::InSync => None,
+            RemoteState::Missing => Some(format!("{} has not been pushed to origin", branch)),
+            RemoteState::Diverged { ahead, behind } => Some(format!(
+                "origin/{} differs from HEAD ({} local and {} remote commits not on the other side), \
+                 the pull request may not match this description",
+                branch, ahead, behind
+            )),
+        };
+        if let Some(problem) = problem {
+            if args.strict {
+                return Err(problem.into());
+            }
+            eprintln!("Warning: {}", problem);
+        }
+    } else if !local_only && !pushed_by_ci {
+        run_with_progress("Pushing to remote", || {
+            check_for_remote(repo)?;
+            push_to_remote(repo, &branch)
+        })?;
+    }
+
+    let mut excluded_reviewers = config.reviewers_exclude.clone();
+    excluded_reviewers.extend(args.reviewers_exclude.iter().cloned());
+    let mut rotation = None;
+    let reviewers = if args.round_robin && existing_pr.is_none() {
+        if config.team.is_empty() {
+            return Err("--round-robin needs a `team` list in .pullrequest.toml".into());
+        }
+        let path = default_rotation_path().ok_or("No data directory to keep the rotation in")?;
+        let author = run_with_progress("Looking up pull request author", || forge.current_user())?;
+        let mut skip = config.out_of_office.clone();
+        skip.push(author);
+        skip.extend(excluded_reviewers.iter().cloned());
+        let key = repo_info(repo)?.slug();
+
+        let mut state = Rotation::load(&path)?;
+        let picked = state.pick(
+            &key,
+            &config.team,
+            &skip,
+            config.round_robin_reviewers.unwrap_or(1),
+        );
+        if picked.is_empty() {
+            eprintln!("Warning: everyone in the review rotation is skipped");
+        }
+        rotation = Some((state, path));
+        picked
+    } else {
+        Vec::new()
+    };
+    if let Some(min) = args.min_reviewers.or(config.min_reviewers) {
+        if existing_pr.is_none() && !local_only {
+            check_min_reviewers(&without_excluded(&reviewers, &excluded_reviewers), min)?;
+        }
+    }
+
+    let context_lines = args.context_lines.unwrap_or(DEFAULT_CONTEXT_LINES);
+    let rename_threshold = args
+        .rename_threshold
+        .or(config.rename_threshold)
+        .unwrap_or(DEFAULT_RENAME_THRESHOLD);
+    let mut diff = run_with_summary(
+        "Getting git diff",
+        || match &args.scope {
+            _ if args.staged => get_staged_diff(repo, false),
+            // Only the changes of the commits in scope
+            Some(scope) => {
+                get_commit_patches(repo, &range, cherry, context_lines, args.diff_algorithm).map(
+                    |patches| {
+                        patches
+                            .into_iter()
+                            .filter(|patch| has_scope(&patch.subject, scope))
+                            .map(|patch| patch.diff)
+                            .collect()
+                    },
+                )
+            }
+            None => get_git_diff(
+                repo,
+                &range,
+                context_lines,
+                args.diff_algorithm,
+                rename_threshold,
+            ),
+        },
+        |diff| {
+            format!(
+                "{} files, {} lines",
+                format_count(file_sections(diff).len()),
+                format_count(diff.lines().count())
+            )
+        },
+    )?;
+    let dependencies = args
+        .summarize_dependencies
+        .then(|| dependency_updates(&diff));
+    let mut exclude = load_ignore_patterns(repo)?;
+    exclude.extend(args.exclude.iter().cloned());
+    if args.summarize_dependencies {
+        // The table says what the lockfile diffs would, in far fewer tokens
+        exclude.extend(LOCKFILES.map(String::from));
+    }
+    let cleaning = DiffCleaning {
+        raw_submodules: args.raw_submodules,
+        include_env_files: args.include_env_files,
+        keep_ansi: args.keep_ansi,
+        exclude: &exclude,
+        max_line_length: args
+            .max_line_length
+            .or(config.max_line_length)
+            .unwrap_or(DEFAULT_MAX_LINE_LENGTH),
+    };
+    let (cleaned, mut filtered) = clean_diff(&diff, &cleaning);
+    diff = cleaned;
+    let run_hash = diff_hash(&diff);
+    let state_path = match default_state_dir() {
+        Some(dir) => Some(RunState::path(&dir, &get_repo_root(repo)?, &branch)),
+        None => None,
+    };
+    let resumed = match &state_path {
+        Some(path) if args.resume => match RunState::load(path)? {
+            Some(state) if state.is_current(&branch, &run_hash, &base) => {
+                status!("Resuming with the title and description of the last run");
+                Some(state)
+            }
+            Some(_) => {
+                eprintln!(
+                    "The diff or base changed since the last run, generating a new description"
+                );
+                None
+            }
+            None => {
+                eprintln!("There is no failed run to resume, generating a new description");
+                None
+            }
+        },
+        None if args.resume => {
+            eprintln!("Warning: no data directory to resume from, generating a new description");
+            None
+        }
+        _ => None,
+    };
+    if !filtered.redacted.is_empty() {
+        let paths: Vec<&str> = filtered
+            .redacted
+            .iter()
+            .map(|(path, _)| path.as_str())
+            .collect();
+        eprintln!(
+            "Warning: redacting possible secrets (use --include-env-files to send them) in: {}",
+            paths.join(", ")
+        );
+    }
+
+    let mut commit_messages = if args.staged {
+        Vec::new()
+    } else {
+        run_with_summary(
+            "Getting commit messages",
+            || get_commit_messages(repo, &range, cherry),
+            |commits| match commits.len() {
+                1 => "1 commit".to_string(),
+                n => format!("{} commits", format_count(n)),
+            },
+        )?
+    };
+
+    if let Some(scope) = &args.scope {
+        commit_messages.retain(|subject| has_scope(subject, scope));
+        if commit_messages.is_empty() {
+            return Err(format!("No commits in {} have the scope `{}`", range, scope).into());
+        }
+    }
+
+    let by_author = if args.by_author || args.by_author_section {
+        let mut groups = get_commits_by_author(repo, &range, cherry)?;
+        if let Some(scope) = &args.scope {
+            for group in &mut groups {
+                group.subjects.retain(|subject| has_scope(subject, scope));
+            }
+            groups.retain(|group| !group.subjects.is_empty());
+        }
+        groups
+    } else {
+        Vec::new()
+    };
+
+    if args.explain {
+        let max_diff_lines = args
+            .max_diff_lines
+            .or(config.max_diff_lines)
+            .unwrap_or(DEFAULT_MAX_DIFF_LINES);
+        let prompt = build_explain_prompt(&truncate_diff(&diff, max_diff_lines), &commit_messages);
+        let (explanation, _) = run_with_elapsed_async(
+            "Explaining the changes",
+            generate_pr_description(&prompt, &model_config),
+        )
+        .await?;
+        println!("{}", sanitize_body(&explanation));
+        return Ok(());
+    }
+
+    let issues = if args.no_issue {
+        Vec::new()
+    } else {
+        detect_issues(&branch, &commit_messages)
+    };
+    if issues.is_empty() && (args.require_issue || (config.require_issue && !args.no_issue)) {
+        return Err(
+            "No linked issue found. Name the branch after the issue (e.g. `123-fix-login` \
+                    or `PROJ-42-fix-login`), or reference it in a commit message (`Fixes #123`, \
+                    `PROJ-42: fix login`)."
+                .into(),
+        );
+    }
+    let jira = args.jira_base_url.map(|base_url| JiraConfig {
+        base_url,
+        token: args.jira_token,
+        email: args.jira_email,
+    });
+
+    let brief_threshold = args
+        .brief_threshold
+        .or(config.brief_threshold)
+        .unwrap_or(DEFAULT_BRIEF_THRESHOLD);
+    let trivial = args.brief
+        && !args.polish
+        && !commit_messages.is_empty()
+        && changed_line_count(&diff) < brief_threshold;
+
+    let stats = if args.file_table || args.no_ai {
+        get_file_stats(repo, &range)?
+    } else {
+        Vec::new()
+    };
+    let pr_description = if let Some(state) = &resumed {
+        state.description.clone()
+    } else if args.no_ai {
+        deterministic_body(&commit_messages, &stats)
+    } else if trivial {
+        eprintln!(
+            "Diff is below the brief threshold, using the commit subjects as the description"
+        );
+        commit_subjects_body(&commit_messages)
+    } else {
+        let skeleton = match &args.body_skeleton {
+            Some(path) => Some(std::fs::read_to_string(path).map_err(|e| {
+                format!("Could not read the body skeleton {}: {}", path.display(), e)
+            })?),
+            None => None,
+        };
+        let template = match args.template_url.or(config.template_url) {
+            Some(url) => load_template(&url).await,
+            None => None,
+        };
+        let style_examples = match args.style_examples {
+            Some(limit) => run_with_progress("Fetching recent pull requests", || {
+                recent_merged_bodies(&forge, limit)
+            })
+            .unwrap_or_else(|e| {
+                eprintln!("Warning: not using style examples: {}", e);
+                Vec::new()
+            }),
+            None => Vec::new(),
+        };
+        let reverts = if args.no_revert_detection || args.staged {
+            Vec::new()
+        } else {
+            let reverts = get_reverts(repo, &range, cherry)?;
+            if reverts.is_empty() {
+                find_inverted_commit(repo, &range, &format!("{}/{}", remote, base))?
+                    .into_iter()
+                    .collect()
+            } else {
+                reverts
+            }
+        };
+        let prompt_options = PromptOptions {
+            append: args.prompt_append,
+            authors: get_commit_authors(repo, &range, cherry)?,
+            audience: args
+                .audience
+                .or(args.since_last_tag.then_some(Audience::ReleaseNotes)),
+            template,
+            max_commits: args.max_commits,
+            test_plan: args
+                .test_plan
+                .then(|| test_paths(&diff).into_iter().map(String::from).collect()),
+            breaking_changes: args.breaking_changes.then(|| removed_public_items(&diff)),
+            style_examples,
+            by_commit: args.by_commit,
+            brief: args.brief,
+            no_diff: args.no_diff,
+            no_commit_messages: args.no_commit_messages || args.staged,
+            structured: args.structured || skeleton.is_some(),
+            reverts,
+            by_author: if args.by_author {
+                by_author.clone()
+            } else {
+                Vec::new()
+            },
+        };
+        if args.dry_run {
+            if let Some(append) = &prompt_options.append {
+                status!("Appending to prompt: {}", append);
+            }
+        }
+        let max_diff_lines = args
+            .max_diff_lines
+            .or(config.max_diff_lines)
+            .unwrap_or(DEFAULT_MAX_DIFF_LINES);
+        let model_diff = if args.by_commit {
+            let mut patches = run_with_progress("Getting per-commit diffs", || {
+                get_commit_patches(repo, &range, cherry, context_lines, args.diff_algorithm)
+            })?;
+            if let Some(scope) = &args.scope {
+                patches.retain(|patch| has_scope(&patch.subject, scope));
+            }
+            for patch in &mut patches {
+                patch.diff = clean_diff(&patch.diff, &cleaning).0;
+            }
+            commit_sections(&patches)
+        } else {
+            diff.clone()
+        };
+        let diff_lines = model_diff.lines().count();
+        let prompt_diff = if prompt_options.no_diff || diff_lines <= max_diff_lines {
+            model_diff
+        } else {
+            let default = args.large_diff.or(config.large_diff).unwrap_or_default();
+            let action = if args.yes || !std::io::stdin().is_terminal() {
+                default
+            } else {
+                ask_large_diff(diff_lines, max_diff_lines)?
+            };
+            match action {
+                LargeDiff::Truncate => {
+                    eprintln!(
+                        "Diff is {} lines, truncating to {}",
+                        diff_lines, max_diff_lines
+                    );
+                    let strategy = args
+                        .truncate_strategy
+                        .or(config.truncate_strategy)
+                        .unwrap_or_default();
+                    let truncated = match strategy {
+                        TruncateStrategy::Tail => truncate_diff(&model_diff, max_diff_lines),
+                        TruncateStrategy::Even => truncate_diff_evenly(&model_diff, max_diff_lines),
+                    };
+                    filtered.truncated = truncated_files(&model_diff, &truncated);
+                    truncated
+                }
+                LargeDiff::Summarize => {
+                    let chunks = diff_chunks(&model_diff, max_diff_lines);
+                    run_with_elapsed_async(
+                        "Summarizing the diff",
+                        summarize_diff(&chunks, &model_config),
+                    )
+                    .await?
+                }
+                LargeDiff::Abort => {
+                    return Err(format!(
+                        "Diff is {} lines, over the {} line limit",
+                        diff_lines, max_diff_lines
+                    )
+                    .into())
+                }
+            }
+        };
+
+        let prompt = if args.polish {
+            let mut draft = String::new();
+            std::io::stdin().read_to_string(&mut draft)?;
+            if draft.trim().is_empty() {
+                return Err("--polish needs a draft description on stdin".into());
+            }
+            build_polish_prompt(&draft, &prompt_diff)
+        } else {
+            build_prompt(&prompt_diff, &commit_messages, &issues, &prompt_options)
+        };
+        if args.print_prompt {
+            eprintln!("{}", prompt);
+            if args.dry_run {
+                return ensure_ready(&preflight_problems);
+            }
+        }
+
+        let (pr_description, model) = run_with_elapsed_async(
+            "Generating PR description",
+            generate_pr_description(&prompt, &model_config),
+        )
+        .await?;
+        eprintln!("Description generated by {}", model);
+        if args.structured || skeleton.is_some() {
+            match parse_structured(&pr_description, &model_config).await {
+                Ok(structured) => match &skeleton {
+                    Some(skeleton) => structured.fill_skeleton(skeleton),
+                    None => structured.to_markdown(),
+                },
+                Err(e) => {
+                    eprintln!("Warning: {}, using it as the description as is", e);
+                    pr_description
+                }
+            }
+        } else {
+            pr_description
+        }
+    };
+    let generated = sanitize_body(&pr_description);
+    let mut pr_description = generated.clone();
+    if let Some(width) = args.wrap_width {
+        pr_description = wrap_body(&pr_description, width.into());
+    }
+    let jira_summaries = match &jira {
+        Some(jira) => fetch_jira_summaries(jira, &issues).await,
+        None => Vec::new(),
+    };
+    pr_description.push_str(&issue_links_block(&issues, jira.as_ref(), &jira_summaries));
+    if args.file_table || args.no_ai {
+        pr_description.push_str(&file_table_block(&stats, args.file_table_rows));
+    }
+    if let Some(updates) = &dependencies {
+        pr_description.push_str(&dependency_updates_block(updates));
+    }
+    if args.by_author_section {
+        pr_description.push_str(&commits_by_author_block(&by_author));
+    }
+    let changed_paths: Vec<&str> = file_sections(&diff)
+        .into_iter()
+        .filter_map(section_path)
+        .collect();
+    let mentions = mentioned_teams(&args.mention, &config.mentions, &changed_paths);
+    pr_description.push_str(&mentions_block(&mentions));
+    if let Some(strategy) = args.merge {
+        pr_description.push_str(&strategy.body_note());
+    }
+    if args.include_diff_in_body {
+        pr_description.push_str(&diff_details_block(&diff));
+    }
+    // --keep-ansi is for the prompt; escape codes never belong in the body
+    pr_description = strip_ansi(&pr_description);
+    if !args.no_tidy {
+        pr_description = tidy_markdown(&pr_description);
+    }
+    let body_max_chars = args
+        .body_max_chars
+        .or(config.body_max_chars)
+        .unwrap_or(DEFAULT_BODY_MAX_CHARS);
+    if let Some(truncated) = truncate_body(&pr_description, body_max_chars) {
+        eprintln!(
+            "Warning: the description is {} characters, cut to fit --body-max-chars {}",
+            pr_description.chars().count(),
+            body_max_chars
+        );
+        pr_description = truncated;
+    }
+    if args.body_only {
+        println!("{}", pr_description);
+        if args.copy {
+            copy_to_clipboard(&pr_description);
+        }
+        return Ok(());
+    }
+    status!("Description: {}", pr_description);
+
+    if args.copy {
+        copy_to_clipboard(&pr_description);
+    }
 
-    if output.status.success() {
-        Ok(String::from_utf8(output.stdout)?.trim().to_string())
+    let projects = if args.projects.is_empty() {
+        config.projects
+    } else {
+        args.projects
+    };
+    let mut fork = None;
+    let mut title = PR_TITLE.to_string();
+    let wip_patterns = config
+        .wip_patterns
+        .unwrap_or_else(|| DEFAULT_WIP_PATTERNS.map(String::from).to_vec());
+    let draft = if args.draft || args.no_draft {
+        args.draft
     } else {
-        Err("Failed to get current branch".into())
+        let wip = is_work_in_progress(
+            &branch,
+            commit_messages.first().map(String::as_str),
+            &wip_patterns,
+        );
+        if wip && existing_pr.is_none() {
+            status!(
+                "{} looks like work in progress, opening a draft (pass --no-draft to request review)",
+                branch
+            );
+        }
+        wip
+    };
+    if existing_pr.is_none() {
+        fork = fork_target(repo, args.head_repo.as_deref(), &branch)?;
+        if let Some(state) = &resumed {
+            title = state.title.clone();
+        } else if let ([subject], true) = (commit_messages.as_slice(), trivial || args.no_ai) {
+            title = subject.clone();
+        } else if args.no_ai || trivial {
+            title = branch_title(&branch);
+        } else {
+            let prompt = build_title_prompt(&pr_description, &commit_messages);
+            match run_with_elapsed_async(
+                "Generating title",
+                generate_pr_description(&prompt, &title_config),
+            )
+            .await
+            {
+                Ok((completion, _)) => title = clean_title(&completion),
+                Err(e) => eprintln!(
+                    "Warning: failed to generate a title, using \"{}\": {}",
+                    title, e
+                ),
+            }
+        }
+        let plan = Plan {
+            base: match &fork {
+                Some(fork) => format!("{}:{}", fork.repo, base),
+                None => base.clone(),
+            },
+            head: fork
+                .as_ref()
+                .map_or(branch.clone(), |fork| fork.head.clone()),
+            title: title.clone(),
+            reviewers: reviewers.clone(),
+            projects: projects.clone(),
+            issues: issues.clone(),
+            draft,
+        };
+        status!("{}", plan.render());
+    }
+    if args.show_filtered {
+        status!("{}", filtered.render());
+    }
+
+    if args.dry_run {
+        ensure_ready(&preflight_problems)?;
+        println!("Ready to create the pull request into {}", base);
+        return Ok(());
+    }
+
+    // Kept until the pull request is written, for --resume after a failure
+    if let Some(path) = &state_path {
+        let state = RunState {
+            diff_hash: run_hash,
+            base: base.clone(),
+            branch: branch.clone(),
+            title: title.clone(),
+            description: generated,
+        };
+        if let Err(e) = state.save(path) {
+            eprintln!("Warning: failed to save the run state: {}", e);
+        }
+    }
+    let clear_state = || {
+        if let Some(path) = &state_path {
+            let _ = std::fs::remove_file(path);
+        }
+    };
+
+    if let Some(pr) = existing_pr {
+        run_with_progress("Updating pull request", || {
+            update_pull_request(&forge, &pr.url, &pr_description)
+        })?;
+        clear_state();
+        if args.print_url {
+            println!("{}", pr.url);
+        } else {
+            println!("Pull request updated: {}", pr.url);
+        }
+
+        if args.ready {
+            if pr.is_draft {
+                run_with_progress("Marking ready for review", || mark_ready(&forge, &pr.url))?;
+                status!("Pull request marked ready for review.");
+            } else {
+                status!("Pull request is already ready for review.");
+            }
+        }
+
+        return Ok(());
+    }
+
+    let metadata = PrMetadata {
+        reviewers,
+        excluded_reviewers,
+        projects,
+        fork,
+        head: ci_branch,
+        draft,
+    };
+    // Issue numbers are the upstream's when the pull request crosses forks
+    let issue_repo = metadata.fork.as_ref().map(|fork| fork.repo.as_str());
+    let pr = run_with_progress_async(
+        "Creating pull request",
+        create_pull_request(&forge, &base, &title, &pr_description, &metadata),
+    )
+    .await?;
+    clear_state();
+    if args.print_url {
+        println!("{}", pr.url);
+    } else {
+        println!("Pull request #{} created: {}", pr.number, pr.url);
+    }
+
+    if let Some((state, path)) = rotation {
+        if let Err(e) = state.save(&path) {
+            eprintln!("Warning: failed to save the review rotation: {}", e);
+        }
+    }
+
+    if let Some(strategy) = args.merge.filter(|_| args.auto_merge) {
+        match run_with_progress("Enabling auto-merge", || {
+            enable_auto_merge(&forge, &pr.url, strategy)
+        }) {
+            Ok(()) => status!("Auto-merge enabled."),
+            Err(e) => eprintln!("Warning: failed to enable auto-merge: {}", e),
+        }
+    }
+
+    if args.self_review {
+        let max_lines = args
+            .max_diff_lines
+            .or(config.max_diff_lines)
+            .unwrap_or(DEFAULT_MAX_DIFF_LINES);
+        match self_review(&forge, &pr.url, &diff, &model_config, max_lines).await {
+            Ok(0) => eprintln!("Warning: the model suggested no self-review comments on the diff"),
+            Ok(posted) => status!("Left {} self-review comments", posted),
+            Err(e) => eprintln!("Warning: failed to leave self-review comments: {}", e),
+        }
+    }
+
+    if args.comment_on_issue {
+        for issue in &issues {
+            if let IssueRef::GitHub(number) = issue {
+                if let Err(e) = comment_on_issue(&forge, issue_repo, *number, &pr.url) {
+                    eprintln!("Warning: failed to comment on #{}: {}", number, e);
+                }
+            }
+        }
     }
+
+    if let Some(webhook) = args.slack_webhook {
+        if let Err(e) = notify_slack(&webhook, &title, &pr.url).await {
+            eprintln!("Warning: failed to send Slack notification: {}", e);
+        }
+    }
+
+    if let Some(hook) = args.post_hook.or(config.post_hook) {
+        let number = pr.number.to_string();
+        let envs = [
+            ("PR_URL", pr.url.as_str()),
+            ("PR_NUMBER", number.as_str()),
+            ("PR_TITLE", title.as_str()),
+        ];
+        match run_with_progress("Running post-hook", || run_hook(repo, &hook, &envs)) {
+            Ok(output) if !output.trim().is_empty() => status!("{}", output.trim_end()),
+            Ok(_) => {}
+            Err(e) => eprintln!("Warning: {}", e),
+        }
+    }
+
+    Ok(())
 }
 
-fn has_remote(branch: &str) -> Result<bool, Box<dyn std::error::Error>> {
-    let output = Command::new("git")
-        .args(&["ls-remote", "--exit-code", "--heads", "origin", branch])
-        .output()?;
+/// Looks up the summaries of the Jira issues when a token is available. Lookup
+/// failures only cost the summary, so they are reported and skipped.
+async fn fetch_jira_summaries(jira: &JiraConfig, issues: &[IssueRef]) -> Vec<(String, String)> {
+    if jira.token.is_none() {
+        return Vec::new();
+    }
 
-    Ok(output.status.success())
+    let mut summaries = Vec::new();
+    for issue in issues {
+        if let IssueRef::Jira(key) = issue {
+            match jira.fetch_summary(key).await {
+                Ok(summary) => summaries.push((key.clone(), summary)),
+                Err(e) => eprintln!("Warning: could not look up Jira issue {}: {}", key, e),
+            }
+        }
+    }
+    summaries
 }
 
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    dotenv().ok();
-    let github_token = std::env::var("GITHUB_TOKEN").expect("no gh key");
-    let anthropic_key = std::env::var("ANTHROPIC_KEY").expect("no anthropic key");
+/// Runs the guards a pull request would have to pass without generating or
+/// creating anything, for a CI step. Every failed guard is reported.
+fn check(
+    repo: &Path,
+    args: &Args,
+    config: &Config,
+    actions: Option<&GithubActions>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let ci_base = actions.and_then(|ci| ci.base_ref.as_deref());
+    let base = resolve_base(
+        repo,
+        args.base.as_deref().or(ci_base).or(config.base.as_deref()),
+    )?;
+    let branch = match actions.and_then(|ci| ci.head_ref.clone()) {
+        Some(branch) => branch,
+        None => get_current_branch(repo)?,
+    };
+    let remote = base_remote(repo)?;
+    let range = base_range(remote, &base);
 
-    // Check for uncommitted changes
-    check_uncommitted_changes()?;
+    let mut problems = Vec::new();
+    if branch == base {
+        problems.push(format!("{} is the base branch", branch));
+    } else if commits_ahead(repo, remote, &base)? == 0 {
+        problems.push(format!("{} has no commits ahead of {}", branch, base));
+    }
+    if !args.skip_dirty_check {
+        if let Err(e) = check_uncommitted_changes(repo) {
+            problems.push(e.to_string());
+        }
+    }
 
-    check_for_remote()?;
+    let commit_messages = get_commit_messages(repo, &range, !args.no_cherry)?;
+    let require_issue = args.require_issue || (config.require_issue && !args.no_issue);
+    if require_issue && detect_issues(&branch, &commit_messages).is_empty() {
+        problems.push("No linked issue found in the branch name or commit messages".to_string());
+    }
 
-    // Push to remote
-    push_to_remote(&get_current_branch()?)?;
+    let mut exclude = load_ignore_patterns(repo)?;
+    exclude.extend(args.exclude.iter().cloned());
+    let cleaning = DiffCleaning {
+        raw_submodules: args.raw_submodules,
+        include_env_files: args.include_env_files,
+        keep_ansi: args.keep_ansi,
+        exclude: &exclude,
+        max_line_length: args
+            .max_line_length
+            .or(config.max_line_length)
+            .unwrap_or(DEFAULT_MAX_LINE_LENGTH),
+    };
+    let context_lines = args.context_lines.unwrap_or(DEFAULT_CONTEXT_LINES);
+    let rename_threshold = args
+        .rename_threshold
+        .or(config.rename_threshold)
+        .unwrap_or(DEFAULT_RENAME_THRESHOLD);
+    let diff = get_git_diff(
+        repo,
+        &range,
+        context_lines,
+        args.diff_algorithm,
+        rename_threshold,
+    )?;
+    let diff_lines = clean_diff(&diff, &cleaning).0.lines().count();
+    let max_diff_lines = args
+        .max_diff_lines
+        .or(config.max_diff_lines)
+        .unwrap_or(DEFAULT_MAX_DIFF_LINES);
+    if diff_lines > max_diff_lines {
+        problems.push(format!(
+            "Diff is {} lines, over the {} line limit",
+            diff_lines, max_diff_lines
+        ));
+    }
 
-    // Get the git diff
-    let diff = get_git_diff()?;
+    if !problems.is_empty() {
+        return Err(format!(
+            "{} is not ready for a pull request into {}:\n  {}",
+            branch,
+            base,
+            problems.join("\n  ")
+        )
+        .into());
+    }
+    println!("{} is ready for a pull request into {}", branch, base);
+    Ok(())
+}
 
-    // Get commit messages
-    let commit_messages = get_commit_messages()?;
+/// Writes starter `.pullrequest.toml` and `.pullrequestignore` files, asking
+/// for the base branch and model when they aren't given and there's a terminal.
+fn init(
+    repo: &Path,
+    base: Option<String>,
+    model: Option<String>,
+    force: bool,
+    yes: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let interactive = !yes && std::io::stdin().is_terminal();
+    let ask = |question: &str, answer: Option<String>, default: String| match answer {
+        Some(answer) => Ok(answer),
+        None if interactive => inquire::Text::new(question).with_default(&default).prompt(),
+        None => Ok(default),
+    };
+    let base = ask("Base branch:", base, get_default_branch(repo)?)?;
+    let model = ask("Model:", model, DEFAULT_MODEL.to_string())?;
 
-    // Get linked issue (if any)
-    let issue = get_linked_issue()?;
+    for path in write_starter_files(repo, &starter_config(&base, &model), force)? {
+        println!("Wrote {}", path.display());
+    }
+    Ok(())
+}
 
-    // Generate PR description using AI
-    println!("Generating AI description with diffs...");
-    let pr_description =
-        generate_pr_description(&diff, &commit_messages, issue, anthropic_key).await?;
-    println!("Description: {}", pr_description);
+/// The `commit-msg` subcommand, kept apart from the pull request pipeline.
+async fn commit_message(
+    repo: &Path,
+    model_config: &ModelConfig,
+    amend: bool,
+    yes: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let diff = run_with_progress("Getting staged changes", || get_staged_diff(repo, amend))?;
+    if diff.trim().is_empty() {
+        return Err("There are no staged changes to commit".into());
+    }
 
-    // Create pull request
-    println!("Creating pull request...");
-    create_pull_request(&pr_description, github_token).await?;
+    let (message, _) = run_with_elapsed_async(
+        "Generating commit message",
+        generate_pr_description(&build_commit_message_prompt(&diff), model_config),
+    )
+    .await?;
+    let message = sanitize_body(&message);
+    println!("{}", message);
 
-    Ok(())
+    if !yes {
+        if !std::io::stdin().is_terminal() {
+            return Err("Pass --yes to commit without a terminal to confirm on".into());
+        }
+        let question = if amend {
+            "Amend the last commit with this message?"
+        } else {
+            "Commit with this message?"
+        };
+        if !inquire::Confirm::new(question)
+            .with_default(false)
+            .prompt()?
+        {
+            println!("Not committed.");
+            return Ok(());
+        }
+    }
+
+    run_with_progress("Committing", || commit(repo, &message, amend))
+}
+
+/// How the diff is cleaned up before it reaches the model.
+struct DiffCleaning<'a> {
+    raw_submodules: bool,
+    include_env_files: bool,
+    keep_ansi: bool,
+    exclude: &'a [String],
+    /// 0 keeps lines of any length.
+    max_line_length: usize,
+}
+
+/// The files cleaning and truncation kept from the model, for `--show-filtered`.
+#[derive(Default)]
+struct Filtered {
+    /// Paths with the pattern that excluded them.
+    excluded: Vec<(String, String)>,
+    /// Paths with how many of their lines were redacted.
+    redacted: Vec<(String, usize)>,
+    truncated: Vec<String>,
+}
+
+impl Filtered {
+    fn render(&self) -> String {
+        let mut report = String::from("Filtered from the diff:");
+        for (path, pattern) in &self.excluded {
+            report.push_str(&format!("\n  {}: excluded by `{}`", path, pattern));
+        }
+        for (path, lines) in &self.redacted {
+            report.push_str(&format!(
+                "\n  {}: {} lines redacted, possible secrets",
+                path, lines
+            ));
+        }
+        for note in &self.truncated {
+            report.push_str(&format!("\n  {}", note));
+        }
+        if self.excluded.is_empty() && self.redacted.is_empty() && self.truncated.is_empty() {
+            report.push_str(" nothing");
+        }
+        report
+    }
 }
 
-fn get_git_diff() -> Result<String, std::io::Error> {
-    let output = Command::new("git")
-        .args(&["diff", "origin/master"])
-        .output()?;
+/// Drops excluded files, summarizes submodule bumps and redacts lines that
+/// look like secrets, unless turned off. Returns the cleaned diff
+/// and what was left out.
+fn clean_diff(diff: &str, cleaning: &DiffCleaning) -> (String, Filtered) {
+    // Escape codes from committed logs or colored output only cost tokens
+    let diff = if cleaning.keep_ansi {
+        diff.to_string()
+    } else {
+        strip_ansi(diff)
+    };
+    let (diff, excluded) = exclude_files(&diff, cleaning.exclude);
+    let diff = match cleaning.max_line_length {
+        0 => diff,
+        max_chars => omit_long_lines(&diff, max_chars),
+    };
+    let diff = if cleaning.raw_submodules {
+        diff
+    } else {
+        describe_submodules(&diff)
+    };
+    let diff = describe_renames(&diff);
+    let (diff, redacted) = if cleaning.include_env_files {
+        (diff, Vec::new())
+    } else {
+        redact_secrets(&diff)
+    };
 
-    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    let filtered = Filtered {
+        excluded,
+        redacted,
+        truncated: Vec::new(),
+    };
+    (diff, filtered)
 }
 
-fn get_commit_messages() -> Result<Vec<String>, std::io::Error> {
-    let output = Command::new("git")
-        .args(&["log", "origin/master..HEAD", "--pretty=format:%s"])
-        .output()?;
+/// Asks the model for comments on the most notable hunks and posts the ones
+/// anchored to a line of the diff, returning how many were posted.
+async fn self_review(
+    forge: &Forge,
+    pr_url: &str,
+    diff: &str,
+    model_config: &ModelConfig,
+    max_lines: usize,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    let prompt = build_self_review_prompt(&truncate_diff(diff, max_lines));
+    let (reply, _) = run_with_elapsed_async(
+        "Writing self-review comments",
+        generate_pr_description(&prompt, model_config),
+    )
+    .await?;
 
-    let messages = String::from_utf8_lossy(&output.stdout)
-        .lines()
-        .map(String::from)
+    let lines = commentable_lines(diff);
+    let comments: Vec<ReviewComment> = parse_review_comments(&reply)
+        .map_err(|e| format!("the reply is not valid JSON: {}", e))?
+        .into_iter()
+        .filter(|comment| {
+            lines
+                .get(&comment.path)
+                .is_some_and(|lines| lines.contains(&comment.line))
+        })
+        .take(4)
         .collect();
+    if comments.is_empty() {
+        return Ok(0);
+    }
 
-    Ok(messages)
+    run_with_progress("Posting self-review comments", || {
+        post_review_comments(forge, pr_url, &comments)
+    })?;
+    Ok(comments.len())
 }
 
-fn get_linked_issue() -> Result<Option<String>, Box<dyn std::error::Error>> {
-    // This function would need to be implemented to fetch the linked issue from GitHub
-    // It might involve parsing commit messages or branch names for issue numbers
-    // and then querying the GitHub API
-    Ok(None)
+/// The read-only checks a dry run makes in place of the writes it skips,
+/// returning what would stop a real run.
+fn preflight(
+    repo: &Path,
+    forge: &Forge,
+    remote: &str,
+    base: &str,
+    head: Option<&str>,
+    updating: bool,
+) -> Vec<String> {
+    let mut problems = Vec::new();
+    if let Err(e) = forge.ensure_supported() {
+        problems.push(e.to_string());
+        return problems;
+    }
+    if let Err(e) = run_with_progress("Checking gh authentication", || forge.check_auth()) {
+        problems.push(e.to_string());
+        return problems;
+    }
+
+    match run_with_progress("Checking base branch", || has_remote(repo, remote, base)) {
+        Ok(true) => {}
+        Ok(false) => problems.push(format!("Base branch {} does not exist on {}", base, remote)),
+        Err(e) => problems.push(e.to_string()),
+    }
+    match run_with_progress("Looking up existing pull request", || {
+        find_pull_request(forge, head)
+    }) {
+        Ok(Some(pr)) if !updating => problems.push(format!(
+            "A pull request is already open for this branch: {} (use --amend to update it)",
+            pr.url
+        )),
+        Ok(None) if updating => {
+            problems.push("There is no open pull request for this branch to update".to_string())
+        }
+        Ok(_) => {}
+        Err(e) => problems.push(e.to_string()),
+    }
+
+    problems
 }
 
-async fn generate_pr_description(
-    diff: &str,
-    commit_messages: &[String],
-    issue: Option<String>,
-    anthropic_key: String,
-) -> Result<String, Box<dyn std::error::Error>> {
-    dotenv().ok();
-    // let client = ApiClient::new()?;
-    let prompt = format!(
-        "Generate a pull request description based on the following information:\n\
-         Diff: {}\n\
-         Commit messages: {}\n\
-         Linked issue: {:?}\n\
-         Please summarize the changes, their purpose, and any potential impact.",
-        diff,
-        commit_messages.join("\n"),
-        issue
-    );
-
-    let claude = ClientBuilder::default()
-        .api_key(anthropic_key)
-        .default_model("claude-3-haiku-20240307".to_string())
-        .build()?;
-
-    let request = CompleteRequestBuilder::default()
-        .prompt(format!("{HUMAN_PROMPT}{}\n{AI_PROMPT}", prompt))
-        .stream(false)
-        .max_tokens_to_sample(1_000_000 as usize)
-        .stop_sequences(vec![HUMAN_PROMPT.to_string()])
-        .build()?;
-    let chat = claude.complete(request).await?;
-    Ok(chat.completion)
-}
-
-async fn create_pull_request(
-    description: &str,
-    _github_token: String,
-) -> Result<(), Box<dyn std::error::Error>> {
-    Command::new("gh")
-        .args(&[
-            "pr",
-            "create",
-            "--title",
-            "Automated Pull Request",
-            "--body",
-            description,
-            "--base",
-            "master",
-        ])
-        .exec();
+/// Fails with every problem [`preflight`] found, if it found any.
+fn ensure_ready(problems: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    if problems.is_empty() {
+        return Ok(());
+    }
+    Err(format!(
+        "Not ready to create the pull request:\n  {}",
+        problems.join("\n  ")
+    )
+    .into())
+}
 
-    Ok(())
+/// The default base, or the user's pick when there is more than one plausible
+/// base. Without a terminal to ask on, the default is used and noted.
+fn choose_base(repo: &Path, yes: bool) -> Result<String, Box<dyn std::error::Error>> {
+    let mut candidates = base_candidates(repo)?;
+    if candidates.len() == 1 {
+        return Ok(candidates.remove(0));
+    }
+
+    if yes || !std::io::stdin().is_terminal() {
+        eprintln!(
+            "Using {} as the base, also found {} (pass --base to choose)",
+            candidates[0],
+            candidates[1..].join(", ")
+        );
+        return Ok(candidates.remove(0));
+    }
+
+    Ok(
+        inquire::Select::new("Which branch should the pull request target?", candidates)
+            .prompt()?,
+    )
+}
+
+/// Asks what to do with a diff over the line limit.
+fn ask_large_diff(lines: usize, max_lines: usize) -> Result<LargeDiff, Box<dyn std::error::Error>> {
+    let truncate = format!("Truncate to the first {} lines", max_lines);
+    let summarize = "Summarize it in parts first (more API calls)".to_string();
+    let abort = "Abort".to_string();
+    let choice = inquire::Select::new(
+        &format!(
+            "The diff is {} lines, over the {} line limit.",
+            lines, max_lines
+        ),
+        vec![truncate.clone(), summarize.clone(), abort],
+    )
+    .prompt()?;
+
+    Ok(if choice == truncate {
+        LargeDiff::Truncate
+    } else if choice == summarize {
+        LargeDiff::Summarize
+    } else {
+        LargeDiff::Abort
+    })
+}
+
+/// The shared prompt template, or `None` to fall back to the built-in
+/// instructions when it can't be fetched or read from the cache.
+async fn load_template(url: &str) -> Option<String> {
+    let Some(cache_dir) = default_cache_dir() else {
+        eprintln!(
+            "Warning: no cache directory, not using the template at {}",
+            url
+        );
+        return None;
+    };
+    match run_with_progress_async("Fetching prompt template", fetch_template(url, &cache_dir)).await
+    {
+        Ok(template) => Some(template),
+        Err(e) => {
+            eprintln!(
+                "Warning: using the default prompt, template unavailable: {}",
+                e
+            );
+            None
+        }
+    }
+}
+
+fn copy_to_clipboard(text: &str) {
+    let result = arboard::Clipboard::new().and_then(|mut clipboard| clipboard.set_text(text));
+
+    match result {
+        Ok(()) => eprintln!("Description copied to clipboard."),
+        Err(e) => {
+            eprintln!("Could not access the clipboard ({}), printing instead:", e);
+            status!("{}", text);
+        }
+    }
 }
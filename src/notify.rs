@@ -0,0 +1,18 @@
+pub async fn notify_slack(
+    webhook: &str,
+    title: &str,
+    pr_url: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let payload = serde_json::json!({
+        "text": format!("Pull request opened: <{}|{}>", pr_url, title),
+    });
+
+    reqwest::Client::new()
+        .post(webhook)
+        .json(&payload)
+        .send()
+        .await?
+        .error_for_status()?;
+
+    Ok(())
+}
@@ -0,0 +1,45 @@
+use crate::issue::IssueRef;
+
+/// Everything the run has resolved about the pull request it is about to
+/// create, so the decisions can be checked in one place before anything is
+/// written.
+#[derive(Debug, Clone, Default)]
+pub struct Plan {
+    pub base: String,
+    pub head: String,
+    pub title: String,
+    pub reviewers: Vec<String>,
+    pub projects: Vec<String>,
+    pub issues: Vec<IssueRef>,
+    pub draft: bool,
+}
+
+impl Plan {
+    pub fn render(&self) -> String {
+        let list = |items: Vec<String>| {
+            if items.is_empty() {
+                "none".to_string()
+            } else {
+                items.join(", ")
+            }
+        };
+        let rows = [
+            ("Base", self.base.clone()),
+            ("Head", self.head.clone()),
+            ("Title", self.title.clone()),
+            ("Reviewers", list(self.reviewers.clone())),
+            ("Projects", list(self.projects.clone())),
+            (
+                "Issues",
+                list(self.issues.iter().map(ToString::to_string).collect()),
+            ),
+            ("Draft", if self.draft { "yes" } else { "no" }.to_string()),
+        ];
+
+        let mut plan = String::from("Plan:");
+        for (label, value) in rows {
+            plan.push_str(&format!("\n  {:<10} {}", format!("{}:", label), value));
+        }
+        plan
+    }
+}
@@ -0,0 +1,128 @@
+use indicatif::{ProgressBar, ProgressStyle};
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// The step currently running, so a timeout can say where it gave up.
+static ACTIVE_STEP: Mutex<Option<(String, ProgressBar)>> = Mutex::new(None);
+
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+/// Hides the spinners and step lines from here on.
+pub fn set_quiet(quiet: bool) {
+    QUIET.store(quiet, Ordering::Relaxed);
+}
+
+/// Starts a ticking spinner for a pipeline step.
+pub fn spinner(message: &str) -> ProgressBar {
+    let pb = if QUIET.load(Ordering::Relaxed) {
+        ProgressBar::hidden()
+    } else {
+        ProgressBar::new_spinner()
+    };
+    pb.set_style(step_style("{spinner:.green} {msg}"));
+    pb.set_message(message.to_string());
+    pb.enable_steady_tick(Duration::from_millis(100));
+    *ACTIVE_STEP.lock().unwrap() = Some((message.to_string(), pb.clone()));
+    pb
+}
+
+/// Stops the spinner of the running step, if any, marking it timed out, and
+/// returns the step's name.
+pub fn abandon_active_step() -> Option<String> {
+    let (message, pb) = ACTIVE_STEP.lock().unwrap().take()?;
+    pb.abandon_with_message(format!("{} Timed out", message));
+    Some(message)
+}
+
+fn clear_active_step() {
+    *ACTIVE_STEP.lock().unwrap() = None;
+}
+
+/// The last tick char is what a finished spinner is left showing.
+fn step_style(template: &str) -> ProgressStyle {
+    ProgressStyle::with_template(template)
+        .unwrap()
+        .tick_chars("⠋⠙⠹⠸⠼⠴⠦⠧⠇⠏✔")
+}
+
+/// Runs `f` as a named step, showing a spinner while it runs and leaving a
+/// "Done"/"Failed" line behind.
+pub fn run_with_progress<T, E>(message: &str, f: impl FnOnce() -> Result<T, E>) -> Result<T, E> {
+    let pb = spinner(message);
+    let result = f();
+    finish(&pb, message, &result);
+    result
+}
+
+/// Like [`run_with_progress`], with `summary` describing the result on the
+/// finished line, e.g. "Getting commit messages Done (7 commits)".
+pub fn run_with_summary<T, E>(
+    message: &str,
+    f: impl FnOnce() -> Result<T, E>,
+    summary: impl FnOnce(&T) -> String,
+) -> Result<T, E> {
+    let pb = spinner(message);
+    let result = f();
+    clear_active_step();
+    match &result {
+        Ok(value) => pb.finish_with_message(format!("{} Done ({})", message, summary(value))),
+        Err(_) => pb.abandon_with_message(format!("{} Failed", message)),
+    }
+    result
+}
+
+/// Formats a count with thousands separators, e.g. `3,402`.
+pub fn format_count(count: usize) -> String {
+    let digits = count.to_string();
+    let mut formatted = String::new();
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            formatted.push(',');
+        }
+        formatted.push(digit);
+    }
+    formatted
+}
+
+/// The async counterpart of [`run_with_progress`].
+pub async fn run_with_progress_async<T, E>(
+    message: &str,
+    future: impl Future<Output = Result<T, E>>,
+) -> Result<T, E> {
+    let pb = spinner(message);
+    let result = future.await;
+    finish(&pb, message, &result);
+    result
+}
+
+/// Like [`run_with_progress_async`], but for slow steps: the spinner shows the
+/// elapsed time while waiting and the finished line records how long it took.
+pub async fn run_with_elapsed_async<T, E>(
+    message: &str,
+    future: impl Future<Output = Result<T, E>>,
+) -> Result<T, E> {
+    let pb = spinner(message);
+    pb.set_style(step_style("{spinner:.green} {msg} ({elapsed})"));
+    let started = Instant::now();
+    let result = future.await;
+    let took = started.elapsed().as_secs_f32();
+
+    // Drop the live elapsed counter now that the final duration is in the message
+    pb.set_style(step_style("{spinner:.green} {msg}"));
+    clear_active_step();
+    match &result {
+        Ok(_) => pb.finish_with_message(format!("{} Done in {:.1}s", message, took)),
+        Err(_) => pb.abandon_with_message(format!("{} Failed after {:.1}s", message, took)),
+    }
+    result
+}
+
+fn finish<T, E>(pb: &ProgressBar, message: &str, result: &Result<T, E>) {
+    clear_active_step();
+    match result {
+        Ok(_) => pb.finish_with_message(format!("{} Done", message)),
+        Err(_) => pb.abandon_with_message(format!("{} Failed", message)),
+    }
+}
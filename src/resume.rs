@@ -0,0 +1,68 @@
+use serde::{Deserialize, Serialize};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::path::{Path, PathBuf};
+
+/// Where run states are kept, `None` when the platform has no data directory.
+pub fn default_state_dir() -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join("pullrequest").join("runs"))
+}
+
+/// A fingerprint of the diff a description was written for. It only has to
+/// match between runs of the same build, so the standard hasher will do.
+pub fn diff_hash(diff: &str) -> String {
+    fingerprint(diff)
+}
+
+fn fingerprint(value: impl Hash) -> String {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    format!("{:016x}", hasher.finish())
+}
+
+/// What a run generated before writing to the forge, kept until the pull
+/// request is created so `--resume` can reuse it after a failure.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RunState {
+    /// [`diff_hash`] of the diff the description is for.
+    pub diff_hash: String,
+    pub base: String,
+    pub branch: String,
+    pub title: String,
+    /// The model's description, before issue links, tables and the other
+    /// additions each run makes.
+    pub description: String,
+}
+
+impl RunState {
+    /// The state file for `branch` of the repository at `repo_root`, named by
+    /// a hash of both since branch names can hold any character.
+    pub fn path(dir: &Path, repo_root: &Path, branch: &str) -> PathBuf {
+        dir.join(format!("{}.json", fingerprint((repo_root, branch))))
+    }
+
+    /// Loads the saved state, `None` when there is none.
+    pub fn load(path: &Path) -> Result<Option<RunState>, Box<dyn std::error::Error>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let contents = std::fs::read_to_string(path)?;
+        serde_json::from_str(&contents)
+            .map(Some)
+            .map_err(|e| format!("Invalid {}: {}", path.display(), e).into())
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Whether the state was saved for this branch, diff and base, and so
+    /// still describes the branch.
+    pub fn is_current(&self, branch: &str, diff_hash: &str, base: &str) -> bool {
+        self.branch == branch && self.diff_hash == diff_hash && self.base == base
+    }
+}
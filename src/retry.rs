@@ -0,0 +1,125 @@
+use std::collections::hash_map::RandomState;
+use std::future::Future;
+use std::hash::{BuildHasher, Hasher};
+use std::time::Duration;
+
+/// How often to try an operation and how long to wait between attempts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Backoff {
+    /// Attempts in total, including the first.
+    pub attempts: u32,
+    /// The wait after the first failure, doubled after each one that follows.
+    pub initial: Duration,
+    /// The longest wait between two attempts.
+    pub max: Duration,
+    /// Waits a random time between half the delay and the full delay, so
+    /// clients that failed together don't all retry at the same moment.
+    pub jitter: bool,
+}
+
+impl Backoff {
+    /// The wait after the failed `attempt`, counting from 1.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let doubling = 2u32.saturating_pow(attempt.saturating_sub(1));
+        let delay = self.initial.saturating_mul(doubling).min(self.max);
+        if !self.jitter {
+            return delay;
+        }
+        let half = delay / 2;
+        let spread = half.as_millis() as u64 + 1;
+        half + Duration::from_millis(random() % spread)
+    }
+}
+
+/// What to do after a failed attempt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Retry {
+    /// Give up and return the error.
+    No,
+    /// Try again after the backoff delay.
+    Yes,
+    /// Try again after this long instead, e.g. as a `Retry-After` header asked.
+    After(Duration),
+}
+
+impl From<bool> for Retry {
+    fn from(retryable: bool) -> Self {
+        if retryable {
+            Retry::Yes
+        } else {
+            Retry::No
+        }
+    }
+}
+
+/// Runs `op` until it succeeds, `should_retry` answers [`Retry::No`] for its
+/// error, or `backoff.attempts` are used up, returning the last result. Both
+/// closures are given the number of the attempt, counting from 1.
+/// `should_retry` is asked about every failure, the last one included, so it
+/// can report each of them.
+pub fn retry<T, E, R>(
+    backoff: &Backoff,
+    mut should_retry: impl FnMut(&E, u32) -> R,
+    mut op: impl FnMut(u32) -> Result<T, E>,
+) -> Result<T, E>
+where
+    R: Into<Retry>,
+{
+    let mut attempt = 1;
+    loop {
+        let error = match op(attempt) {
+            Ok(value) => return Ok(value),
+            Err(error) => error,
+        };
+        match next_delay(backoff, should_retry(&error, attempt).into(), attempt) {
+            Some(delay) if attempt < backoff.attempts => std::thread::sleep(delay),
+            _ => return Err(error),
+        }
+        attempt += 1;
+    }
+}
+
+/// [`retry`] for async operations, waiting with the tokio timer.
+pub async fn retry_async<T, E, R, F>(
+    backoff: &Backoff,
+    mut should_retry: impl FnMut(&E, u32) -> R,
+    mut op: impl FnMut(u32) -> F,
+) -> Result<T, E>
+where
+    R: Into<Retry>,
+    F: Future<Output = Result<T, E>>,
+{
+    let mut attempt = 1;
+    loop {
+        let error = match op(attempt).await {
+            Ok(value) => return Ok(value),
+            Err(error) => error,
+        };
+        match next_delay(backoff, should_retry(&error, attempt).into(), attempt) {
+            Some(delay) if attempt < backoff.attempts => tokio::time::sleep(delay).await,
+            _ => return Err(error),
+        }
+        attempt += 1;
+    }
+}
+
+fn next_delay(backoff: &Backoff, retry: Retry, attempt: u32) -> Option<Duration> {
+    match retry {
+        Retry::No => None,
+        Retry::Yes => Some(backoff.delay(attempt)),
+        Retry::After(delay) => Some(delay),
+    }
+}
+
+/// A random number from the standard library's per-process hash seed, plenty
+/// for spreading out retries.
+fn random() -> u64 {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos() as u64,
+    );
+    hasher.finish()
+}
@@ -0,0 +1,103 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// Where the round-robin positions are kept, `None` when the platform has no
+/// data directory.
+pub fn default_rotation_path() -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join("pullrequest").join("rotation.json"))
+}
+
+/// The next position in each team's review rotation, keyed by `owner/repo`.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Rotation {
+    next: BTreeMap<String, usize>,
+}
+
+impl Rotation {
+    /// Loads the saved rotation, starting fresh when there is none yet.
+    pub fn load(path: &Path) -> Result<Rotation, Box<dyn std::error::Error>> {
+        if !path.exists() {
+            return Ok(Rotation::default());
+        }
+
+        let contents = std::fs::read_to_string(path)?;
+        serde_json::from_str(&contents)
+            .map_err(|e| format!("Invalid {}: {}", path.display(), e).into())
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Picks up to `count` reviewers from `team` for `key`, continuing where the
+    /// last pick left off and skipping anyone in `skip`. The rotation only moves
+    /// past the people picked, so someone skipped while out is next in line
+    /// when they're back.
+    pub fn pick(
+        &mut self,
+        key: &str,
+        team: &[String],
+        skip: &[String],
+        count: usize,
+    ) -> Vec<String> {
+        if team.is_empty() {
+            return Vec::new();
+        }
+
+        let start = self.next.get(key).copied().unwrap_or_default() % team.len();
+        let mut picked = Vec::new();
+        let mut next = start;
+        for offset in 0..team.len() {
+            if picked.len() == count {
+                break;
+            }
+            let index = (start + offset) % team.len();
+            let member = &team[index];
+            if !skip.contains(member) {
+                picked.push(member.clone());
+                next = index + 1;
+            }
+        }
+
+        self.next.insert(key.to_string(), next % team.len());
+        picked
+    }
+}
+
+/// `reviewers` without anyone in `excluded`, compared case-insensitively and
+/// ignoring a leading `@`, since GitHub logins are case-insensitive.
+pub fn without_excluded(reviewers: &[String], excluded: &[String]) -> Vec<String> {
+    let login = |name: &str| name.trim().trim_start_matches('@').to_lowercase();
+    let excluded: Vec<String> = excluded.iter().map(|name| login(name)).collect();
+    reviewers
+        .iter()
+        .filter(|reviewer| !excluded.contains(&login(reviewer)))
+        .cloned()
+        .collect()
+}
+
+/// Fails when fewer than `min` reviewers were resolved, listing those that were.
+pub fn check_min_reviewers(reviewers: &[String], min: usize) -> Result<(), String> {
+    if reviewers.len() >= min {
+        return Ok(());
+    }
+    let resolved = if reviewers.is_empty() {
+        "none were resolved".to_string()
+    } else {
+        format!(
+            "only {} resolved: {}",
+            reviewers.len(),
+            reviewers.join(", ")
+        )
+    };
+    Err(format!(
+        "At least {} reviewers are required, but {}. Add reviewers with --round-robin \
+         and the `team` in .pullrequest.toml, or lower --min-reviewers.",
+        min, resolved
+    ))
+}
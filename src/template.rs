@@ -0,0 +1,69 @@
+use reqwest::header::{ETAG, IF_NONE_MATCH};
+use reqwest::StatusCode;
+use std::path::{Path, PathBuf};
+
+/// Where fetched templates are cached, `None` when the platform has no cache
+/// directory.
+pub fn default_cache_dir() -> Option<PathBuf> {
+    dirs::cache_dir().map(|dir| dir.join("pullrequest").join("templates"))
+}
+
+/// Fetches the prompt template at `url`, revalidating the cached copy with its
+/// ETag. When the server can't be reached the cached copy is used; it is only
+/// an error when there is neither.
+pub async fn fetch_template(
+    url: &str,
+    cache_dir: &Path,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let name: String = url
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    let body_path = cache_dir.join(format!("{}.txt", name));
+    let etag_path = cache_dir.join(format!("{}.etag", name));
+    let cached = std::fs::read_to_string(&body_path).ok();
+
+    let mut request = reqwest::Client::new().get(url);
+    if let (Some(_), Ok(etag)) = (&cached, std::fs::read_to_string(&etag_path)) {
+        request = request.header(IF_NONE_MATCH, etag);
+    }
+
+    let fetched = match request.send().await {
+        Ok(response) if response.status() == StatusCode::NOT_MODIFIED => None,
+        Ok(response) if response.status().is_success() => {
+            let etag = response
+                .headers()
+                .get(ETAG)
+                .and_then(|etag| etag.to_str().ok())
+                .map(str::to_string);
+            match response.text().await {
+                Ok(body) => Some((body, etag)),
+                Err(e) if cached.is_none() => return Err(e.into()),
+                Err(_) => None,
+            }
+        }
+        Ok(response) if cached.is_none() => {
+            return Err(format!("Fetching {} failed with {}", url, response.status()).into())
+        }
+        Err(e) if cached.is_none() => return Err(e.into()),
+        _ => None,
+    };
+
+    let Some((body, etag)) = fetched else {
+        return Ok(cached.unwrap_or_default());
+    };
+
+    // A failed cache write shouldn't stop this run from using the template
+    if std::fs::create_dir_all(cache_dir).is_ok() && std::fs::write(&body_path, &body).is_ok() {
+        match etag {
+            Some(etag) => {
+                let _ = std::fs::write(&etag_path, etag);
+            }
+            None => {
+                let _ = std::fs::remove_file(&etag_path);
+            }
+        }
+    }
+
+    Ok(body)
+}
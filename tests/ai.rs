@@ -0,0 +1,531 @@
+use pullrequest::ai::{
+    build_commit_message_prompt, build_explain_prompt, build_polish_prompt, build_prompt,
+    build_title_prompt, clean_title, commit_sections, generate_pr_description, parse_api_base,
+    parse_review_comments, parse_structured, summarize_diff, Audience, ModelConfig, PromptOptions,
+    StructuredDescription,
+};
+use pullrequest::git::{AuthorCommits, CommitPatch, Revert};
+use pullrequest::issue::IssueRef;
+use serde_json::json;
+use wiremock::matchers::{header, method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+fn config(server: &MockServer, models: &[&str]) -> ModelConfig {
+    let mut config = ModelConfig::new(
+        "test-key".to_string(),
+        models.iter().map(|m| m.to_string()).collect(),
+    );
+    config.api_base = server.uri();
+    config
+}
+
+fn completion(text: &str) -> ResponseTemplate {
+    ResponseTemplate::new(200).set_body_json(json!({
+        "completion": text,
+        "stop_reason": "stop_sequence",
+    }))
+}
+
+fn api_error(status: u16, error_type: &str) -> ResponseTemplate {
+    ResponseTemplate::new(status).set_body_json(json!({
+        "error": { "type": error_type, "message": "something went wrong" },
+    }))
+}
+
+#[tokio::test]
+async fn prompt_contains_diff_commits_and_issue() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/v1/complete"))
+        .and(header("x-api-key", "test-key"))
+        .respond_with(completion("A fine description"))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let commits = vec!["Add widget".to_string(), "Fix widget".to_string()];
+    let prompt = build_prompt(
+        "+fn widget() {}",
+        &commits,
+        &[IssueRef::GitHub(42), IssueRef::Jira("PROJ-7".to_string())],
+        &PromptOptions::default(),
+    );
+    let (description, model) = generate_pr_description(&prompt, &config(&server, &["model-a"]))
+        .await
+        .unwrap();
+
+    assert_eq!(description, "A fine description");
+    assert_eq!(model, "model-a");
+
+    let requests = server.received_requests().await.unwrap();
+    let body: serde_json::Value = requests[0].body_json().unwrap();
+    let prompt = body["prompt"].as_str().unwrap();
+    assert_eq!(body["model"], "model-a");
+    assert!(prompt.contains("+fn widget() {}"));
+    assert!(prompt.contains("Add widget\nFix widget"));
+    assert!(prompt.contains("Linked issues: #42, PROJ-7"));
+}
+
+#[tokio::test]
+async fn large_diffs_are_summarized_chunk_by_chunk() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/v1/complete"))
+        .respond_with(completion(" - changed a file\n"))
+        .expect(2)
+        .mount(&server)
+        .await;
+
+    let chunks = vec!["+first".to_string(), "+second".to_string()];
+    let summary = summarize_diff(&chunks, &config(&server, &["model-a"]))
+        .await
+        .unwrap();
+
+    assert_eq!(
+        summary,
+        "(too long to include, summarized in 2 parts)\n- changed a file\n- changed a file"
+    );
+}
+
+#[tokio::test]
+async fn custom_prompt_wrappers_are_sent() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .respond_with(completion("A fine description"))
+        .mount(&server)
+        .await;
+    let mut config = config(&server, &["model-a"]);
+    config.human_prompt = "\n\nUser:".to_string();
+    config.ai_prompt = "\n\nBot:".to_string();
+    config.stop_sequences = vec!["\n\nUser:".to_string(), "END".to_string()];
+
+    generate_pr_description("diff", &config).await.unwrap();
+
+    let requests = server.received_requests().await.unwrap();
+    let body: serde_json::Value = requests[0].body_json().unwrap();
+    assert_eq!(body["prompt"], "\n\nUser:diff\n\n\nBot:");
+    assert_eq!(body["stop_sequences"], json!(["\n\nUser:", "END"]));
+}
+
+#[tokio::test(start_paused = true)]
+async fn server_error_is_retried_then_reported() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/v1/complete"))
+        .respond_with(api_error(500, "api_error"))
+        .expect(3)
+        .mount(&server)
+        .await;
+
+    let result = generate_pr_description("diff", &config(&server, &["model-a"])).await;
+
+    let error = result.unwrap_err().to_string();
+    assert!(error.contains("all models are unavailable"), "{}", error);
+}
+
+#[tokio::test(start_paused = true)]
+async fn overloaded_model_falls_back_to_the_next() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(wiremock::matchers::body_partial_json(
+            json!({ "model": "model-a" }),
+        ))
+        .respond_with(api_error(529, "overloaded_error"))
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(wiremock::matchers::body_partial_json(
+            json!({ "model": "model-b" }),
+        ))
+        .respond_with(completion("From the fallback"))
+        .mount(&server)
+        .await;
+
+    let (description, model) =
+        generate_pr_description("diff", &config(&server, &["model-a", "model-b"]))
+            .await
+            .unwrap();
+
+    assert_eq!(description, "From the fallback");
+    assert_eq!(model, "model-b");
+}
+
+#[tokio::test(start_paused = true)]
+async fn rate_limit_waits_for_retry_after() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .respond_with(api_error(429, "rate_limit_error").insert_header("retry-after", "30"))
+        .up_to_n_times(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .respond_with(completion("After waiting"))
+        .mount(&server)
+        .await;
+
+    let started = tokio::time::Instant::now();
+    let (description, _) = generate_pr_description("diff", &config(&server, &["model-a"]))
+        .await
+        .unwrap();
+
+    assert_eq!(description, "After waiting");
+    assert!(started.elapsed() >= std::time::Duration::from_secs(30));
+}
+
+#[tokio::test(start_paused = true)]
+async fn empty_completion_is_retried_then_reported() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .respond_with(completion("  \n"))
+        .expect(3)
+        .mount(&server)
+        .await;
+
+    let result = generate_pr_description("diff", &config(&server, &["model-a"])).await;
+
+    let error = result.unwrap_err().to_string();
+    assert!(
+        error.contains("no model returned a usable description, the last one was 0 characters"),
+        "{}",
+        error
+    );
+}
+
+#[tokio::test]
+async fn malformed_structured_reply_is_sent_back_for_repair() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .respond_with(completion(
+            "```json\n{\"summary\": \"Fix login\", \"changes\": [\"Retry\"], \"impact\": \"\"}\n```",
+        ))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let structured = parse_structured(
+        "{\"summary\": \"Fix login\",",
+        &config(&server, &["model-a"]),
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(
+        structured.to_markdown(),
+        "## Summary\nFix login\n\n## Changes\n- Retry"
+    );
+    let requests = server.received_requests().await.unwrap();
+    let body: serde_json::Value = requests[0].body_json().unwrap();
+    assert!(body["prompt"]
+        .as_str()
+        .unwrap()
+        .contains("Reply:\n{\"summary\": \"Fix login\","));
+}
+
+#[tokio::test]
+async fn invalid_request_is_not_retried() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .respond_with(api_error(400, "invalid_request_error"))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let result = generate_pr_description("diff", &config(&server, &["model-a", "model-b"])).await;
+
+    assert!(result.is_err());
+}
+
+#[test]
+fn appended_instruction_ends_the_prompt() {
+    let options = PromptOptions {
+        append: Some("Emphasize the security implications.".to_string()),
+        ..Default::default()
+    };
+
+    let prompt = build_prompt("diff", &[], &[], &options);
+
+    assert!(prompt.ends_with("\nEmphasize the security implications."));
+}
+
+#[test]
+fn audience_replaces_the_default_instructions() {
+    let default = build_prompt("diff", &[], &[], &PromptOptions::default());
+    let options = PromptOptions {
+        audience: Some(Audience::Changelog),
+        ..Default::default()
+    };
+
+    let prompt = build_prompt("diff", &[], &[], &options);
+
+    assert!(default.contains("Please summarize the changes"));
+    assert!(!prompt.contains("Please summarize the changes"));
+    assert!(prompt.contains("changelog entry for end users"));
+}
+
+#[test]
+fn commits_beyond_the_cap_are_counted_not_listed() {
+    let commits: Vec<String> = (1..=5).rev().map(|n| format!("Commit {}", n)).collect();
+    let options = PromptOptions {
+        max_commits: Some(2),
+        ..Default::default()
+    };
+
+    let prompt = build_prompt("diff", &commits, &[], &options);
+
+    assert!(prompt.contains("Commit messages: Commit 5\nCommit 4\n... and 3 earlier commits\n"));
+    assert!(!prompt.contains("Commit 3"));
+}
+
+#[test]
+fn reverts_are_named_in_the_prompt() {
+    let options = PromptOptions {
+        reverts: vec![Revert {
+            subject: "Cache sessions (#41)".to_string(),
+            sha: Some("0123456789abcdef0123".to_string()),
+        }],
+        ..Default::default()
+    };
+
+    let prompt = build_prompt("diff", &[], &[], &options);
+
+    assert!(prompt.contains("reverts \"Cache sessions (#41)\" (0123456789ab)."));
+    assert!(!build_prompt("diff", &[], &[], &PromptOptions::default()).contains("reverts"));
+}
+
+#[test]
+fn brief_asks_for_a_sentence_or_two() {
+    let options = PromptOptions {
+        brief: true,
+        ..Default::default()
+    };
+
+    let prompt = build_prompt("diff", &[], &[], &options);
+
+    assert!(prompt.contains("one or two concise sentences"));
+}
+
+#[test]
+fn api_base_must_be_an_http_url() {
+    assert_eq!(
+        parse_api_base("https://gateway.internal/anthropic/").unwrap(),
+        "https://gateway.internal/anthropic"
+    );
+    assert!(parse_api_base("gateway.internal").is_err());
+    assert!(parse_api_base("ftp://gateway.internal").is_err());
+}
+
+#[test]
+fn omitted_sections_are_left_out_of_the_prompt() {
+    let commits = vec!["Add feature".to_string()];
+    let without_diff = PromptOptions {
+        no_diff: true,
+        ..Default::default()
+    };
+    let without_commits = PromptOptions {
+        no_commit_messages: true,
+        ..Default::default()
+    };
+
+    let prompt = build_prompt("+added line", &commits, &[], &without_diff);
+    assert!(!prompt.contains("Diff:"));
+    assert!(prompt.contains("Commit messages: Add feature"));
+
+    let prompt = build_prompt("+added line", &commits, &[], &without_commits);
+    assert!(prompt.contains("Diff: +added line"));
+    assert!(!prompt.contains("Commit messages:"));
+}
+
+#[test]
+fn template_replaces_the_default_instructions() {
+    let options = PromptOptions {
+        template: Some("Use the team format.\n".to_string()),
+        ..Default::default()
+    };
+
+    let prompt = build_prompt("diff", &[], &[], &options);
+
+    assert!(prompt.ends_with("Linked issues: none\nUse the team format."));
+}
+
+#[test]
+fn test_plan_without_test_changes_says_so() {
+    let options = PromptOptions {
+        test_plan: Some(Vec::new()),
+        ..Default::default()
+    };
+
+    let prompt = build_prompt("diff", &[], &[], &options);
+
+    assert!(prompt.contains("\"## Test Plan\" section containing only \"No tests added/changed.\""));
+}
+
+#[test]
+fn polish_prompt_carries_the_draft_and_diff() {
+    let prompt = build_polish_prompt("  fixes the widget thing\n", "+fn widget() {}");
+
+    assert!(prompt.contains("preserving its meaning"));
+    assert!(prompt.contains("Description:\nfixes the widget thing\nDiff: +fn widget() {}"));
+}
+
+#[test]
+fn breaking_changes_lists_removed_declarations() {
+    let options = PromptOptions {
+        breaking_changes: Some(vec!["pub fn parse(input: &str)".to_string()]),
+        ..Default::default()
+    };
+
+    let prompt = build_prompt("diff", &[], &[], &options);
+
+    assert!(prompt.contains("\"## Breaking Changes\" section"));
+    assert!(prompt.ends_with("likely candidates:\npub fn parse(input: &str)"));
+}
+
+#[test]
+fn style_examples_are_fenced_in_the_prompt() {
+    let options = PromptOptions {
+        style_examples: vec!["## Summary\nOne".to_string(), "## Summary\nTwo".to_string()],
+        ..Default::default()
+    };
+
+    let prompt = build_prompt("diff", &[], &[], &options);
+
+    assert!(prompt.ends_with("repository:\n---\n## Summary\nOne\n---\n## Summary\nTwo\n---"));
+}
+
+#[test]
+fn by_commit_prompt_labels_each_commit() {
+    let patches = [
+        CommitPatch {
+            subject: "Add a".to_string(),
+            diff: "+a\n".to_string(),
+        },
+        CommitPatch {
+            subject: "Add b".to_string(),
+            diff: "+b\n".to_string(),
+        },
+    ];
+    let options = PromptOptions {
+        by_commit: true,
+        ..Default::default()
+    };
+
+    let prompt = build_prompt(&commit_sections(&patches), &[], &[], &options);
+
+    assert!(prompt.contains("Diff: \nCommit: Add a\n+a\n\nCommit: Add b\n+b\n"));
+    assert!(prompt.contains("one \"### <commit subject>\" section per commit"));
+}
+
+#[test]
+fn commit_message_prompt_asks_for_a_conventional_commit() {
+    let prompt = build_commit_message_prompt("+fn widget() {}");
+
+    assert!(prompt.contains("Conventional Commits"));
+    assert!(prompt.ends_with("Diff: +fn widget() {}"));
+}
+
+#[test]
+fn several_authors_are_credited() {
+    let options = PromptOptions {
+        authors: vec!["Ana".to_string(), "Bo".to_string()],
+        ..Default::default()
+    };
+
+    let prompt = build_prompt("diff", &[], &[], &options);
+
+    assert!(prompt.contains("Linked issues: none\nAuthors: Ana, Bo (a collaborative branch"));
+}
+
+#[test]
+fn commit_messages_are_listed_under_their_authors() {
+    let options = PromptOptions {
+        by_author: vec![
+            AuthorCommits {
+                author: "Ana".to_string(),
+                subjects: vec!["Add retries".to_string()],
+            },
+            AuthorCommits {
+                author: "Bo".to_string(),
+                subjects: vec!["Fix typo".to_string()],
+            },
+        ],
+        ..Default::default()
+    };
+
+    let prompt = build_prompt("diff", &["Add retries".to_string()], &[], &options);
+
+    assert!(!prompt.contains("Commit messages: "));
+    assert!(prompt.contains("Commit messages by author:\nAna:\n- Add retries\nBo:\n- Fix typo\n"));
+}
+
+#[test]
+fn structured_description_renders_every_section() {
+    let structured = StructuredDescription::parse(
+        r#"Here you go: {"summary": "Add retries", "changes": ["Retry pushes", "Log attempts"],
+        "impact": "Fewer failed runs.", "test_plan": "cargo test"}"#,
+    )
+    .unwrap();
+
+    assert_eq!(
+        structured.to_markdown(),
+        "## Summary\nAdd retries\n\n## Changes\n- Retry pushes\n- Log attempts\n\n\
+         ## Impact\nFewer failed runs.\n\n## Test Plan\ncargo test"
+    );
+}
+
+#[test]
+fn skeleton_markers_are_filled_and_the_rest_kept() {
+    let structured = StructuredDescription {
+        summary: "Add retries".to_string(),
+        changes: vec!["Retry pushes".to_string(), "Log attempts".to_string()],
+        impact: String::new(),
+        test_plan: None,
+    };
+    let skeleton = "## What\n{{summary}}\n\n## How\n{{changes}}\n\n## Testing\n{{test_plan}}\n\n\
+                    - [ ] Docs updated {{unknown}}\n";
+
+    assert_eq!(
+        structured.fill_skeleton(skeleton),
+        "## What\nAdd retries\n\n## How\n- Retry pushes\n- Log attempts\n\n## Testing\n\
+         Nothing specific to test.\n\n- [ ] Docs updated {{unknown}}\n"
+    );
+}
+
+#[test]
+fn review_comments_are_parsed_from_the_reply() {
+    let comments = parse_review_comments(
+        "Sure:\n[{\"path\": \"src/lib.rs\", \"line\": 12, \"body\": \"Deliberately lossy.\"}]",
+    )
+    .unwrap();
+
+    assert_eq!(comments.len(), 1);
+    assert_eq!(comments[0].path, "src/lib.rs");
+    assert_eq!(comments[0].line, 12);
+}
+
+#[test]
+fn title_prompt_is_built_from_the_description() {
+    let prompt = build_title_prompt("## Summary\nRetries pushes", &["Retry pushes".to_string()]);
+
+    assert!(prompt.contains("under 72 characters"));
+    assert!(
+        prompt.ends_with("Commit messages: Retry pushes\nDescription:\n## Summary\nRetries pushes")
+    );
+}
+
+#[test]
+fn titles_lose_labels_quotes_and_periods() {
+    assert_eq!(
+        clean_title("\nTitle: \"Retry failed pushes.\"\nMore"),
+        "Retry failed pushes"
+    );
+    assert_eq!(clean_title("# Add --scope"), "Add --scope");
+}
+
+#[test]
+fn explain_prompt_asks_for_an_explanation_not_a_description() {
+    let prompt = build_explain_prompt("+fn retry() {}", &["Add retry".to_string()]);
+
+    assert!(prompt.starts_with("Explain these changes to a reviewer"));
+    assert!(prompt.contains("This is not a pull request description"));
+    assert!(prompt.ends_with("Commit messages: Add retry\nDiff: +fn retry() {}"));
+}
@@ -0,0 +1,262 @@
+mod common;
+
+use common::TestRepo;
+use pullrequest::body::{
+    branch_title, commits_by_author_block, dependency_updates_block, deterministic_body,
+    file_table_block, mentioned_teams, mentions_block, sanitize_body, strip_ansi, tidy_markdown,
+    truncate_body, wrap_body,
+};
+use pullrequest::diff::DependencyUpdate;
+use pullrequest::git::{AuthorCommits, FileStat};
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::process::Command;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+#[test]
+fn sanitize_strips_ansi_and_control_characters() {
+    let body = "\x1b[1mSummary\x1b[0m\r\n\nDetails\x1b]8;;https://x\x07link\x1b]8;;\x07\x08\n\n";
+
+    assert_eq!(sanitize_body(body), "Summary\n\nDetailslink");
+}
+
+#[test]
+fn sanitize_leaves_whitespace_and_blank_lines_alone() {
+    let body = "Summary  \n\n\n```\nfn a() {}  \n\n\n\tb();\n```";
+
+    assert_eq!(sanitize_body(body), body);
+}
+
+#[test]
+fn strip_ansi_keeps_diff_lines_intact() {
+    let diff = "+\x1b[32mok\x1b[0m 3 passed\n-\x1b[31mFAILED\x1b[0m\n";
+
+    assert_eq!(strip_ansi(diff), "+ok 3 passed\n-FAILED\n");
+}
+
+#[test]
+fn wrap_breaks_prose_at_width() {
+    let body = "one two three four five six seven eight nine ten";
+
+    assert_eq!(
+        wrap_body(body, 20),
+        "one two three four\nfive six seven eight\nnine ten"
+    );
+}
+
+#[test]
+fn wrap_indents_list_continuations() {
+    let body = "- alpha beta gamma delta epsilon\n1. alpha beta gamma delta epsilon";
+
+    assert_eq!(
+        wrap_body(body, 20),
+        "- alpha beta gamma\n  delta epsilon\n1. alpha beta gamma\n   delta epsilon"
+    );
+}
+
+#[test]
+fn wrap_leaves_code_tables_and_headings_alone() {
+    let long = "word ".repeat(10);
+    let body = format!(
+        "```\n{long}\n```\n| {long} |\n# {long}\n    {long}",
+        long = long.trim_end()
+    );
+
+    assert_eq!(wrap_body(&body, 20), body);
+}
+
+#[test]
+fn wrap_never_splits_links_or_inline_code() {
+    let body = "see [the long link text](https://example.com/a b) and `some inline code` here";
+
+    assert_eq!(
+        wrap_body(body, 20),
+        "see\n[the long link text](https://example.com/a b)\nand\n`some inline code`\nhere"
+    );
+}
+
+fn stat(path: &str, added: Option<u64>, removed: Option<u64>) -> FileStat {
+    FileStat {
+        path: path.to_string(),
+        added,
+        removed,
+    }
+}
+
+#[test]
+fn file_table_lists_counts_and_caps_rows() {
+    let stats = vec![
+        stat("src/a.rs", Some(10), Some(2)),
+        stat("logo.png", None, None),
+        stat("src/b.rs", Some(1), Some(0)),
+    ];
+
+    assert_eq!(
+        file_table_block(&stats, 2),
+        "\n\n| File | Added | Removed |\n\
+         | ---- | ----: | ------: |\n\
+         | `src/a.rs` | +10 | -2 |\n\
+         | `logo.png` | binary | binary |\n\
+         \n... 1 more files"
+    );
+    assert_eq!(file_table_block(&[], 2), "");
+}
+
+#[test]
+fn mentions_combine_explicit_and_path_mapped_teams() {
+    let by_path = BTreeMap::from([
+        ("src/api/".to_string(), vec!["@org/api".to_string()]),
+        ("docs/".to_string(), vec!["org/docs".to_string()]),
+    ]);
+
+    let teams = mentioned_teams(
+        &["org/security".to_string(), "@org/api".to_string()],
+        &by_path,
+        &["src/api/routes.rs", "README.md"],
+    );
+
+    assert_eq!(teams, vec!["@org/security", "@org/api"]);
+    assert_eq!(mentions_block(&teams), "\n\ncc @org/security @org/api");
+    assert_eq!(mentions_block(&[]), "");
+}
+
+#[test]
+fn tidy_spaces_headings_and_leaves_code_alone() {
+    let body =
+        "## Summary  \nFixes #12.\n#123 is related\n\n\n\n## Details\n```diff\n+a  \n\n\n```\nDone";
+
+    assert_eq!(
+        tidy_markdown(body),
+        "## Summary\n\nFixes #12.\n#123 is related\n\n## Details\n\n```diff\n+a  \n\n\n```\nDone"
+    );
+}
+
+#[test]
+fn body_within_the_limit_is_not_truncated() {
+    assert_eq!(truncate_body("## Summary\n\nShort.", 100), None);
+}
+
+#[test]
+fn long_body_is_cut_on_a_line_with_a_marker() {
+    let body = format!("## Summary\n\n{}", "A line of prose.\n".repeat(20));
+
+    let truncated = truncate_body(&body, 100).unwrap();
+
+    assert!(truncated.chars().count() <= 100, "{}", truncated);
+    assert!(truncated.starts_with("## Summary\n\nA line of prose.\n"));
+    assert!(truncated.ends_with("A line of prose.\n\n[description truncated]"));
+}
+
+#[test]
+fn fence_left_open_by_the_cut_is_closed() {
+    let body = format!("Diff:\n\n```diff\n{}```", "+added\n".repeat(50));
+
+    let truncated = truncate_body(&body, 80).unwrap();
+
+    assert!(truncated.chars().count() <= 80, "{}", truncated);
+    assert!(truncated.ends_with("+added\n```\n\n[description truncated]"));
+}
+
+#[test]
+fn dependency_updates_render_as_a_table() {
+    let updates = [
+        DependencyUpdate {
+            name: "serde".to_string(),
+            from: Some("1.0.190".to_string()),
+            to: Some("1.0.193".to_string()),
+        },
+        DependencyUpdate {
+            name: "thiserror".to_string(),
+            from: None,
+            to: Some("1.0.50".to_string()),
+        },
+    ];
+
+    assert_eq!(
+        dependency_updates_block(&updates),
+        "\n\n## Dependency Updates\n\n| Package | From | To |\n| ------- | ---- | -- |\n\
+         | `serde` | 1.0.190 | 1.0.193 |\n| `thiserror` | added | 1.0.50 |"
+    );
+    assert_eq!(dependency_updates_block(&[]), "");
+}
+
+#[test]
+fn deterministic_body_lists_commits_and_stats() {
+    let commits = ["Add login page".to_string(), "Fix typo".to_string()];
+    let stats = [
+        FileStat {
+            path: "src/login.rs".to_string(),
+            added: Some(40),
+            removed: Some(2),
+        },
+        FileStat {
+            path: "logo.png".to_string(),
+            added: None,
+            removed: None,
+        },
+    ];
+
+    assert_eq!(
+        deterministic_body(&commits, &stats),
+        "## Commits\n\n- Add login page\n- Fix typo\n\n## Changes\n\n\
+         2 files changed, 40 insertions(+), 2 deletions(-)"
+    );
+}
+
+#[test]
+fn branch_names_become_titles() {
+    assert_eq!(branch_title("feature/add-login_page"), "Add login page");
+    assert_eq!(branch_title("hotfix"), "Hotfix");
+}
+
+#[test]
+fn commits_by_author_get_a_section_each() {
+    let groups = [
+        AuthorCommits {
+            author: "Ana".to_string(),
+            subjects: vec!["Add retries".to_string(), "Log attempts".to_string()],
+        },
+        AuthorCommits {
+            author: "Bo".to_string(),
+            subjects: vec!["Fix typo".to_string()],
+        },
+    ];
+
+    assert_eq!(
+        commits_by_author_block(&groups),
+        "\n\n## Commits by Author\n\n### Ana\n\n- Add retries\n- Log attempts\n\n### Bo\n\n- Fix typo"
+    );
+    assert_eq!(commits_by_author_block(&[]), "");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn no_tidy_keeps_the_model_output_as_written() {
+    let description = "Adds a.\n\n\n```\nfn a() {}  \n\n\n\tb();\n```";
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/v1/complete"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "completion": description,
+            "stop_reason": "stop_sequence",
+        })))
+        .mount(&server)
+        .await;
+    let repo = TestRepo::new();
+    repo.commit("a.txt", "a\n", "Add a");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_pullrequest"))
+        .args(["--body-only", "--no-tidy", "--no-issue", "--base", "master"])
+        .args(["--host", "github.com"])
+        .current_dir(repo.path())
+        .env("ANTHROPIC_KEY", "test-key")
+        .env("ANTHROPIC_BASE_URL", server.uri())
+        .env_remove("GITHUB_ACTIONS")
+        .output()
+        .expect("run pullrequest");
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{}", stderr);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.starts_with(description), "{}", stdout);
+}
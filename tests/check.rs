@@ -0,0 +1,51 @@
+mod common;
+
+use common::TestRepo;
+use std::process::{Command, Output};
+
+fn check(repo: &TestRepo, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_pullrequest"))
+        .args(["check", "--base", "master"])
+        .args(args)
+        .current_dir(repo.path())
+        .env_remove("GITHUB_ACTIONS")
+        .env_remove("GITHUB_BASE_REF")
+        .env_remove("GITHUB_HEAD_REF")
+        .output()
+        .expect("run pullrequest")
+}
+
+#[test]
+fn check_lists_every_failure_and_exits_with_1() {
+    let repo = TestRepo::new();
+    repo.commit("a.txt", "1\n2\n3\n", "Add a");
+    std::fs::write(repo.path().join("a.txt"), "dirty\n").unwrap();
+
+    let output = check(&repo, &["--require-issue", "--max-diff-lines", "2"]);
+
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("feature is not ready for a pull request into master"),
+        "{}",
+        stderr
+    );
+    assert!(stderr.contains("uncommitted changes"), "{}", stderr);
+    assert!(stderr.contains("No linked issue found"), "{}", stderr);
+    assert!(stderr.contains("over the 2 line limit"), "{}", stderr);
+}
+
+#[test]
+fn check_passes_with_the_dirty_tree_allowed() {
+    let repo = TestRepo::new();
+    repo.commit("a.txt", "a\n", "Add a");
+    std::fs::write(repo.path().join("a.txt"), "dirty\n").unwrap();
+
+    let output = check(&repo, &["--skip-dirty-check"]);
+
+    assert_eq!(output.status.code(), Some(0), "{:?}", output);
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "feature is ready for a pull request into master\n"
+    );
+}
@@ -0,0 +1,36 @@
+use pullrequest::ci::GithubActions;
+
+fn vars<'a>(pairs: &'a [(&'a str, &'a str)]) -> impl Fn(&str) -> Option<String> + 'a {
+    move |name| {
+        pairs
+            .iter()
+            .find(|(key, _)| *key == name)
+            .map(|(_, value)| value.to_string())
+    }
+}
+
+#[test]
+fn pull_request_events_give_base_and_head() {
+    let actions = GithubActions::from_vars(vars(&[
+        ("GITHUB_ACTIONS", "true"),
+        ("GITHUB_BASE_REF", "main"),
+        ("GITHUB_HEAD_REF", "fix-login"),
+    ]));
+
+    assert_eq!(
+        actions,
+        Some(GithubActions {
+            base_ref: Some("main".to_string()),
+            head_ref: Some("fix-login".to_string()),
+        })
+    );
+}
+
+#[test]
+fn push_events_leave_the_refs_unset() {
+    let actions =
+        GithubActions::from_vars(vars(&[("GITHUB_ACTIONS", "true"), ("GITHUB_BASE_REF", "")]));
+
+    assert_eq!(actions, Some(GithubActions::default()));
+    assert_eq!(GithubActions::from_vars(vars(&[])), None);
+}
@@ -0,0 +1,64 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use tempfile::TempDir;
+
+/// A throwaway repository with a bare `origin` remote. `master` is pushed to
+/// origin and a `feature` branch is checked out on top of it.
+pub struct TestRepo {
+    _root: TempDir,
+    path: PathBuf,
+}
+
+impl TestRepo {
+    pub fn new() -> Self {
+        let root = tempfile::tempdir().expect("create temp dir");
+        let origin = root.path().join("origin.git");
+        let path = root.path().join("work");
+
+        git(
+            root.path(),
+            &["init", "--bare", "-b", "master", "origin.git"],
+        );
+        git(root.path(), &["init", "-b", "master", "work"]);
+        git(&path, &["config", "user.name", "Test User"]);
+        git(&path, &["config", "user.email", "test@example.com"]);
+        git(&path, &["config", "commit.gpgsign", "false"]);
+        git(
+            &path,
+            &["remote", "add", "origin", origin.to_str().unwrap()],
+        );
+
+        let repo = TestRepo { _root: root, path };
+        repo.commit("README.md", "# fixture\n", "Initial commit");
+        git(&repo.path, &["push", "-q", "origin", "master"]);
+        git(&repo.path, &["checkout", "-q", "-b", "feature"]);
+        repo
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn commit(&self, file: &str, contents: &str, message: &str) {
+        fs::write(self.path.join(file), contents).expect("write fixture file");
+        git(&self.path, &["add", file]);
+        git(&self.path, &["commit", "-q", "-m", message]);
+    }
+}
+
+pub fn git(dir: &Path, args: &[&str]) -> String {
+    let output = Command::new("git")
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .expect("run git");
+    assert!(
+        output.status.success(),
+        "git {:?} failed: {}",
+        args,
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8_lossy(&output.stdout).to_string()
+}
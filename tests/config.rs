@@ -0,0 +1,114 @@
+mod common;
+
+use common::TestRepo;
+use pullrequest::config::{
+    load_ignore_patterns, starter_config, write_starter_files, Config, STARTER_IGNORE,
+};
+
+#[test]
+fn missing_config_uses_defaults() {
+    let repo = TestRepo::new();
+
+    let config = Config::load(repo.path()).unwrap();
+
+    assert!(config.pre_hook.is_none());
+}
+
+#[test]
+fn config_is_read_from_repo_root() {
+    let repo = TestRepo::new();
+    repo.commit(
+        ".pullrequest.toml",
+        "pre_hook = \"cargo test\"\n",
+        "Add config",
+    );
+    std::fs::create_dir(repo.path().join("nested")).unwrap();
+
+    let config = Config::load(&repo.path().join("nested")).unwrap();
+
+    assert_eq!(config.pre_hook.as_deref(), Some("cargo test"));
+}
+
+#[test]
+fn base_can_be_pinned() {
+    let repo = TestRepo::new();
+    repo.commit(".pullrequest.toml", "base = \"develop\"\n", "Add config");
+
+    let config = Config::load(repo.path()).unwrap();
+
+    assert_eq!(config.base.as_deref(), Some("develop"));
+}
+
+#[test]
+fn unknown_keys_are_rejected() {
+    let repo = TestRepo::new();
+    repo.commit(".pullrequest.toml", "pre_hokk = \"typo\"\n", "Add config");
+
+    assert!(Config::load(repo.path()).is_err());
+}
+
+#[test]
+fn empty_stop_sequences_are_rejected() {
+    let repo = TestRepo::new();
+    repo.commit(
+        ".pullrequest.toml",
+        "stop_sequences = [\"\\n\\nHuman:\", \"\"]\n",
+        "Add config",
+    );
+
+    let error = Config::load(repo.path()).unwrap_err().to_string();
+
+    assert!(
+        error.contains("stop_sequences can't be empty strings"),
+        "{}",
+        error
+    );
+}
+
+#[test]
+fn ignore_patterns_skip_comments_and_blank_lines() {
+    let repo = TestRepo::new();
+    assert!(load_ignore_patterns(repo.path()).unwrap().is_empty());
+
+    repo.commit(
+        ".pullrequestignore",
+        "# generated\n*.lock\n\n  dist/  \n",
+        "Add ignore file",
+    );
+
+    assert_eq!(
+        load_ignore_patterns(repo.path()).unwrap(),
+        vec!["*.lock", "dist/"]
+    );
+}
+
+#[test]
+fn starter_files_load_back() {
+    let repo = TestRepo::new();
+
+    let written =
+        write_starter_files(repo.path(), &starter_config("develop", "claude-x"), false).unwrap();
+
+    assert_eq!(written.len(), 2);
+    let config = Config::load(repo.path()).unwrap();
+    assert_eq!(config.base.as_deref(), Some("develop"));
+    assert_eq!(config.model.as_deref(), Some("claude-x"));
+    assert!(load_ignore_patterns(repo.path())
+        .unwrap()
+        .contains(&"Cargo.lock".to_string()));
+}
+
+#[test]
+fn starter_files_are_not_overwritten_without_force() {
+    let repo = TestRepo::new();
+    repo.commit(".pullrequestignore", "dist/\n", "Ignore dist");
+
+    let error = write_starter_files(repo.path(), &starter_config("main", "m"), false).unwrap_err();
+
+    assert!(error.to_string().contains("pass --force"), "{}", error);
+    assert!(!repo.path().join(".pullrequest.toml").exists());
+
+    write_starter_files(repo.path(), &starter_config("main", "m"), true).unwrap();
+    let ignore = std::fs::read_to_string(repo.path().join(".pullrequestignore")).unwrap();
+    assert_eq!(ignore, STARTER_IGNORE);
+}
@@ -0,0 +1,39 @@
+use pullrequest::credentials::{resolve_api_key, ApiKey, KeySource};
+
+#[test]
+fn key_flag_takes_precedence_over_the_command() {
+    let source = KeySource {
+        key: Some("sk-from-flag".parse().unwrap()),
+        command: Some("echo sk-from-command".to_string()),
+        ..Default::default()
+    };
+
+    assert_eq!(resolve_api_key(&source).unwrap(), "sk-from-flag");
+}
+
+#[test]
+fn key_file_is_read_and_trimmed() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("anthropic");
+    std::fs::write(&path, "sk-from-file\n").unwrap();
+
+    let source = KeySource {
+        key_file: Some(path.clone()),
+        ..Default::default()
+    };
+    assert_eq!(resolve_api_key(&source).unwrap(), "sk-from-file");
+
+    std::fs::write(&path, "\n").unwrap();
+    let error = resolve_api_key(&source).unwrap_err().to_string();
+    assert!(error.contains("is empty"), "{}", error);
+}
+
+#[test]
+fn key_is_hidden_from_debug_output() {
+    let source = KeySource {
+        key: Some(ApiKey("sk-secret".to_string())),
+        ..Default::default()
+    };
+
+    assert!(!format!("{:?}", source).contains("sk-secret"));
+}
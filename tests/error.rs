@@ -0,0 +1,29 @@
+use pullrequest::error::{exit_code, gh_failure, gh_spawn_failure};
+use std::io::{Error, ErrorKind};
+
+#[test]
+fn missing_gh_points_at_the_install_page() {
+    let error = gh_spawn_failure(Error::from(ErrorKind::NotFound));
+
+    assert!(error.to_string().contains("https://cli.github.com"));
+    assert_eq!(exit_code(&error), 5);
+}
+
+#[test]
+fn logged_out_gh_points_at_auth_login() {
+    let stderr = b"To get started with GitHub CLI, please run:  gh auth login\n";
+
+    let error = gh_failure("pr create", stderr);
+
+    assert!(error.to_string().contains("gh is not logged in"));
+}
+
+#[test]
+fn other_gh_failures_keep_their_stderr() {
+    let error = gh_failure("pr create", b"GraphQL: Head sha can't be blank\n");
+
+    assert_eq!(
+        error.to_string(),
+        "gh pr create failed: GraphQL: Head sha can't be blank"
+    );
+}
@@ -0,0 +1,107 @@
+mod common;
+
+use common::TestRepo;
+use pullrequest::forge::{
+    cross_fork, parse_pr_url, parse_remote_url, remote_host, resolve_forge, CreatedPr, ForkTarget,
+    MergeStrategy, RepoInfo,
+};
+
+fn info(host: &str, owner: &str, name: &str) -> Option<RepoInfo> {
+    Some(RepoInfo {
+        host: host.to_string(),
+        owner: owner.to_string(),
+        name: name.to_string(),
+    })
+}
+
+#[test]
+fn repo_info_comes_from_ssh_and_https_remotes() {
+    assert_eq!(
+        parse_remote_url("git@github.com:bennyhodl/pullrequest.git"),
+        info("github.com", "bennyhodl", "pullrequest")
+    );
+    assert_eq!(
+        parse_remote_url("https://github.example.com/team/service"),
+        info("github.example.com", "team", "service")
+    );
+    assert_eq!(
+        parse_remote_url("ssh://git@gitlab.com:2222/group/sub/project.git/"),
+        info("gitlab.com", "group/sub", "project")
+    );
+}
+
+#[test]
+fn unrecognizable_remotes_have_no_repo_info() {
+    assert_eq!(parse_remote_url("/srv/git/project.git"), None);
+    assert_eq!(parse_remote_url("https://github.com/"), None);
+    assert_eq!(
+        remote_host("https://user@GitHub.com/owner/repo"),
+        Some("github.com".to_string())
+    );
+}
+
+#[test]
+fn fork_pull_requests_target_upstream_with_an_owner_head() {
+    let origin = info("github.com", "me", "pullrequest").unwrap();
+    let upstream = info("github.com", "bennyhodl", "pullrequest").unwrap();
+
+    assert_eq!(
+        cross_fork(&origin, Some(&upstream), None, "fix-login"),
+        Some(ForkTarget {
+            repo: "bennyhodl/pullrequest".to_string(),
+            head: "me:fix-login".to_string(),
+        })
+    );
+    assert_eq!(
+        cross_fork(&upstream, None, Some("me"), "fix-login").map(|fork| fork.head),
+        Some("me:fix-login".to_string())
+    );
+    assert_eq!(cross_fork(&origin, None, None, "fix-login"), None);
+}
+
+#[test]
+fn pr_urls_give_the_repo_and_number() {
+    assert_eq!(
+        parse_pr_url("https://github.com/bennyhodl/pullrequest/pull/12"),
+        Some(("bennyhodl/pullrequest".to_string(), 12))
+    );
+    assert_eq!(
+        parse_pr_url("https://github.com/bennyhodl/pullrequest"),
+        None
+    );
+}
+
+#[test]
+fn merge_strategy_is_noted_in_the_body() {
+    assert_eq!(
+        MergeStrategy::Squash.body_note(),
+        "\n\nMerge method: squash"
+    );
+    assert_eq!(
+        MergeStrategy::Merge.body_note(),
+        "\n\nMerge method: merge commit"
+    );
+}
+
+#[test]
+fn created_pr_is_read_from_the_last_line_of_gh_output() {
+    let stdout = "\nCreating pull request for feature into master in owner/repo\n\nhttps://github.com/owner/repo/pull/42\n";
+
+    assert_eq!(
+        CreatedPr::from_gh_output(stdout),
+        Some(CreatedPr {
+            number: 42,
+            url: "https://github.com/owner/repo/pull/42".to_string(),
+        })
+    );
+    assert_eq!(CreatedPr::from_gh_output("no url here\n"), None);
+}
+
+#[test]
+fn gh_runs_in_the_repository() {
+    let repo = TestRepo::new();
+
+    let forge = resolve_forge(repo.path(), None, Some("github.com".to_string())).unwrap();
+
+    assert_eq!(forge.gh().get_current_dir(), Some(repo.path()));
+}
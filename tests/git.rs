@@ -0,0 +1,664 @@
+mod common;
+
+use common::{git, TestRepo};
+use pullrequest::error::exit_code;
+use pullrequest::git::{
+    base_candidates, base_range, base_remote, check_for_remote, check_uncommitted_changes, commit,
+    commits_ahead, find_inverted_commit, get_commit_authors, get_commit_messages,
+    get_commit_patches, get_commits_by_author, get_current_branch, get_default_branch,
+    get_file_stats, get_git_diff, get_head_sha, get_reverts, get_staged_diff, has_remote,
+    has_scope, is_work_in_progress, latest_tag, push_to_remote, remote_state, resolve_base,
+    restore_stashes, stash_changes, validate_range, AuthorCommits, DiffAlgorithm, RemoteState,
+    DEFAULT_CONTEXT_LINES, DEFAULT_RENAME_THRESHOLD, DEFAULT_WIP_PATTERNS,
+};
+use std::sync::Mutex;
+
+/// `restore_stashes` pops the stashes of every test in the process.
+static STASHES: Mutex<()> = Mutex::new(());
+
+#[test]
+fn current_branch_is_the_checked_out_branch() {
+    let repo = TestRepo::new();
+
+    assert_eq!(get_current_branch(repo.path()).unwrap(), "feature");
+}
+
+#[test]
+fn head_sha_follows_new_commits() {
+    let repo = TestRepo::new();
+    let before = get_head_sha(repo.path()).unwrap();
+
+    repo.commit("b.txt", "b\n", "Add b");
+
+    let after = get_head_sha(repo.path()).unwrap();
+    assert_ne!(before, after);
+    assert_eq!(after, git(repo.path(), &["rev-parse", "HEAD"]).trim());
+}
+
+#[test]
+fn commits_ahead_counts_branch_commits() {
+    let repo = TestRepo::new();
+    assert_eq!(commits_ahead(repo.path(), "origin", "master").unwrap(), 0);
+
+    repo.commit("b.txt", "b\n", "Add b");
+
+    assert_eq!(commits_ahead(repo.path(), "origin", "master").unwrap(), 1);
+}
+
+#[test]
+fn forks_count_commits_against_upstream() {
+    let repo = TestRepo::new();
+    assert_eq!(base_remote(repo.path()).unwrap(), "origin");
+
+    let upstream = tempfile::tempdir().unwrap();
+    let upstream_path = upstream.path().to_str().unwrap();
+    git(repo.path(), &["init", "-q", "--bare", upstream_path]);
+    repo.commit("b.txt", "b\n", "Add b");
+    git(
+        repo.path(),
+        &["push", "-q", upstream_path, "HEAD:refs/heads/master"],
+    );
+    git(repo.path(), &["remote", "add", "upstream", upstream_path]);
+    git(repo.path(), &["fetch", "-q", "upstream"]);
+    repo.commit("c.txt", "c\n", "Add c");
+
+    assert_eq!(base_remote(repo.path()).unwrap(), "upstream");
+    assert_eq!(commits_ahead(repo.path(), "origin", "master").unwrap(), 2);
+    assert_eq!(commits_ahead(repo.path(), "upstream", "master").unwrap(), 1);
+}
+
+#[test]
+fn commit_messages_only_include_branch_commits() {
+    let repo = TestRepo::new();
+    repo.commit("a.txt", "a\n", "Add a");
+    repo.commit("b.txt", "b\n", "Add b");
+
+    let messages =
+        get_commit_messages(repo.path(), &base_range("origin", "master"), false).unwrap();
+
+    assert_eq!(messages, vec!["Add b", "Add a"]);
+}
+
+#[test]
+fn commit_patches_pair_each_commit_with_its_own_diff() {
+    let repo = TestRepo::new();
+    repo.commit("a.txt", "a\n", "Add a");
+    repo.commit("b.txt", "b\n", "Add b");
+
+    let patches = get_commit_patches(
+        repo.path(),
+        &base_range("origin", "master"),
+        false,
+        DEFAULT_CONTEXT_LINES,
+        None,
+    )
+    .unwrap();
+
+    let subjects: Vec<_> = patches.iter().map(|p| p.subject.as_str()).collect();
+    assert_eq!(subjects, ["Add a", "Add b"]);
+    assert!(patches[0].diff.contains("+++ b/a.txt"));
+    assert!(!patches[0].diff.contains("b.txt"));
+    assert!(patches[1].diff.contains("+++ b/b.txt"));
+}
+
+#[test]
+fn commit_messages_are_empty_without_branch_commits() {
+    let repo = TestRepo::new();
+
+    assert!(
+        get_commit_messages(repo.path(), &base_range("origin", "master"), false)
+            .unwrap()
+            .is_empty()
+    );
+}
+
+#[test]
+fn diff_is_against_origin_master() {
+    let repo = TestRepo::new();
+    repo.commit("README.md", "# fixture\nfeature line\n", "Extend readme");
+
+    let diff = get_git_diff(
+        repo.path(),
+        &base_range("origin", "master"),
+        DEFAULT_CONTEXT_LINES,
+        None,
+        DEFAULT_RENAME_THRESHOLD,
+    )
+    .unwrap();
+
+    assert!(diff.contains("diff --git a/README.md b/README.md"));
+    assert!(diff.contains("+feature line"));
+    assert!(!diff.contains("-# fixture"));
+}
+
+#[test]
+fn context_lines_control_the_surrounding_lines() {
+    let repo = TestRepo::new();
+    repo.commit("list.txt", "1\n2\n3\n4\n5\n", "Add list");
+    repo.commit("list.txt", "1\n2\nthree\n4\n5\n", "Change 3");
+
+    let diff = get_git_diff(
+        repo.path(),
+        "HEAD~1..HEAD",
+        0,
+        None,
+        DEFAULT_RENAME_THRESHOLD,
+    )
+    .unwrap();
+    assert!(diff.contains("-3\n+three\n"));
+    assert!(!diff.contains(" 2\n"));
+
+    let diff = get_git_diff(
+        repo.path(),
+        "HEAD~1..HEAD",
+        1,
+        None,
+        DEFAULT_RENAME_THRESHOLD,
+    )
+    .unwrap();
+    assert!(diff.contains(" 2\n-3\n+three\n 4\n"));
+    assert!(!diff.contains(" 1\n"));
+}
+
+#[test]
+fn diff_algorithm_is_passed_to_git() {
+    let repo = TestRepo::new();
+    repo.commit("list.txt", "a\nb\nc\na\nb\nb\na\n", "Add list");
+    repo.commit("list.txt", "c\nb\na\nb\na\nc\n", "Shuffle list");
+
+    let myers = get_git_diff(
+        repo.path(),
+        "HEAD~1..HEAD",
+        0,
+        Some(DiffAlgorithm::Myers),
+        DEFAULT_RENAME_THRESHOLD,
+    )
+    .unwrap();
+    let histogram = get_git_diff(
+        repo.path(),
+        "HEAD~1..HEAD",
+        0,
+        Some(DiffAlgorithm::Histogram),
+        DEFAULT_RENAME_THRESHOLD,
+    )
+    .unwrap();
+    assert!(myers.contains("@@ -4 +1,0 @@"));
+    assert!(histogram.contains("@@ -4,2 +1,0 @@"));
+}
+
+#[test]
+fn file_stats_count_lines_per_file() {
+    let repo = TestRepo::new();
+    repo.commit("README.md", "# fixture\nfeature line\n", "Extend readme");
+    repo.commit("a.txt", "a\nb\n", "Add a");
+
+    let stats = get_file_stats(repo.path(), &base_range("origin", "master")).unwrap();
+
+    let counts: Vec<_> = stats
+        .iter()
+        .map(|s| (s.path.as_str(), s.added, s.removed))
+        .collect();
+    assert_eq!(
+        counts,
+        vec![("README.md", Some(1), Some(0)), ("a.txt", Some(2), Some(0))]
+    );
+}
+
+#[test]
+fn check_for_remote_pushes_a_new_branch() {
+    let repo = TestRepo::new();
+    repo.commit("a.txt", "a\n", "Add a");
+    assert!(!has_remote(repo.path(), "origin", "feature").unwrap());
+
+    check_for_remote(repo.path()).unwrap();
+
+    assert!(has_remote(repo.path(), "origin", "feature").unwrap());
+}
+
+#[test]
+fn non_fast_forward_push_suggests_force_with_lease() {
+    let repo = TestRepo::new();
+    repo.commit("a.txt", "a\n", "Add a");
+    push_to_remote(repo.path(), "feature").unwrap();
+    git(repo.path(), &["reset", "-q", "--hard", "HEAD~1"]);
+    repo.commit("b.txt", "b\n", "Add b");
+
+    let error = push_to_remote(repo.path(), "feature").unwrap_err();
+
+    assert!(error.to_string().contains("--force-with-lease"));
+}
+
+#[test]
+fn remote_state_reports_unpushed_commits() {
+    let repo = TestRepo::new();
+    repo.commit("a.txt", "a\n", "Add a");
+    assert_eq!(
+        remote_state(repo.path(), "feature").unwrap(),
+        RemoteState::Missing
+    );
+
+    push_to_remote(repo.path(), "feature").unwrap();
+    assert_eq!(
+        remote_state(repo.path(), "feature").unwrap(),
+        RemoteState::InSync
+    );
+
+    repo.commit("b.txt", "b\n", "Add b");
+    assert_eq!(
+        remote_state(repo.path(), "feature").unwrap(),
+        RemoteState::Diverged {
+            ahead: 1,
+            behind: 0
+        }
+    );
+}
+
+#[test]
+fn clean_tree_passes_the_uncommitted_changes_check() {
+    let repo = TestRepo::new();
+
+    assert!(check_uncommitted_changes(repo.path()).is_ok());
+}
+
+#[test]
+fn dirty_tree_exits_with_uncommitted_changes_code() {
+    let repo = TestRepo::new();
+    std::fs::write(repo.path().join("dirty.txt"), "wip\n").unwrap();
+
+    let error = check_uncommitted_changes(repo.path()).unwrap_err();
+
+    assert_eq!(exit_code(error.as_ref()), 2);
+}
+
+#[test]
+fn outside_a_repo_exits_with_not_a_repo_code() {
+    let dir = tempfile::tempdir().unwrap();
+
+    let error = check_uncommitted_changes(dir.path()).unwrap_err();
+
+    assert_eq!(exit_code(error.as_ref()), 3);
+}
+
+#[test]
+fn explicit_range_limits_diff_and_log() {
+    let repo = TestRepo::new();
+    repo.commit("a.txt", "a\n", "Add a");
+    repo.commit("b.txt", "b\n", "Add b");
+
+    let range = "HEAD~1..HEAD";
+    validate_range(repo.path(), range).unwrap();
+
+    assert_eq!(
+        get_commit_messages(repo.path(), range, false).unwrap(),
+        vec!["Add b"]
+    );
+    let diff = get_git_diff(
+        repo.path(),
+        range,
+        DEFAULT_CONTEXT_LINES,
+        None,
+        DEFAULT_RENAME_THRESHOLD,
+    )
+    .unwrap();
+    assert!(diff.contains("b.txt"));
+    assert!(!diff.contains("a.txt"));
+}
+
+#[test]
+fn invalid_ranges_are_rejected() {
+    let repo = TestRepo::new();
+
+    assert!(validate_range(repo.path(), "master").is_err());
+    assert!(validate_range(repo.path(), "master..").is_err());
+    assert!(validate_range(repo.path(), "master..nope").is_err());
+}
+
+#[test]
+fn base_defaults_to_the_remote_default_branch() {
+    let repo = TestRepo::new();
+    assert_eq!(resolve_base(repo.path(), None).unwrap(), "master");
+
+    git(repo.path(), &["remote", "set-head", "origin", "master"]);
+    git(repo.path(), &["checkout", "-q", "-b", "develop", "master"]);
+    git(repo.path(), &["push", "-q", "origin", "develop"]);
+    git(repo.path(), &["remote", "set-head", "origin", "develop"]);
+
+    assert_eq!(resolve_base(repo.path(), None).unwrap(), "develop");
+    assert_eq!(
+        resolve_base(repo.path(), Some("release")).unwrap(),
+        "release"
+    );
+}
+
+#[test]
+fn auto_base_follows_the_upstream_branch() {
+    let repo = TestRepo::new();
+    repo.commit("a.txt", "a\n", "Add a");
+    git(repo.path(), &["push", "-q", "origin", "feature"]);
+    git(
+        repo.path(),
+        &[
+            "checkout",
+            "-q",
+            "-b",
+            "stacked",
+            "--track",
+            "origin/feature",
+        ],
+    );
+
+    assert_eq!(resolve_base(repo.path(), Some("auto")).unwrap(), "feature");
+}
+
+#[test]
+fn auto_base_ignores_a_branch_tracking_itself() {
+    let repo = TestRepo::new();
+    git(repo.path(), &["push", "-q", "-u", "origin", "feature"]);
+
+    assert_eq!(resolve_base(repo.path(), Some("auto")).unwrap(), "master");
+}
+
+#[test]
+fn cherry_drops_commits_already_on_base() {
+    let repo = TestRepo::new();
+    repo.commit("a.txt", "a\n", "Add a");
+    repo.commit("b.txt", "b\n", "Add b");
+    git(repo.path(), &["checkout", "-q", "master"]);
+    repo.commit("c.txt", "c\n", "Add c");
+    git(repo.path(), &["cherry-pick", "feature~1"]);
+    git(repo.path(), &["push", "-q", "origin", "master"]);
+    git(repo.path(), &["checkout", "-q", "feature"]);
+
+    let range = base_range("origin", "master");
+
+    assert_eq!(
+        get_commit_messages(repo.path(), &range, false).unwrap(),
+        vec!["Add b", "Add a"]
+    );
+    assert_eq!(
+        get_commit_messages(repo.path(), &range, true).unwrap(),
+        vec!["Add b"]
+    );
+}
+
+#[test]
+fn main_and_master_are_both_base_candidates() {
+    let repo = TestRepo::new();
+    assert_eq!(base_candidates(repo.path()).unwrap(), ["master"]);
+
+    git(repo.path(), &["push", "-q", "origin", "master:main"]);
+
+    assert_eq!(base_candidates(repo.path()).unwrap(), ["master", "main"]);
+}
+
+#[test]
+fn default_branch_falls_back_to_main_when_origin_has_no_master() {
+    let repo = TestRepo::new();
+    git(repo.path(), &["push", "-q", "origin", "master:main"]);
+    git(
+        repo.path(),
+        &["update-ref", "-d", "refs/remotes/origin/master"],
+    );
+
+    assert_eq!(get_default_branch(repo.path()).unwrap(), "main");
+    assert_eq!(base_candidates(repo.path()).unwrap(), ["main"]);
+}
+
+#[test]
+fn staged_changes_are_committed_with_the_message() {
+    let repo = TestRepo::new();
+    std::fs::write(repo.path().join("a.txt"), "a\n").unwrap();
+    git(repo.path(), &["add", "a.txt"]);
+
+    assert!(get_staged_diff(repo.path(), false)
+        .unwrap()
+        .contains("+++ b/a.txt"));
+    commit(repo.path(), "feat: add a", false).unwrap();
+
+    assert_eq!(
+        git(repo.path(), &["log", "-1", "--format=%s"]).trim(),
+        "feat: add a"
+    );
+    assert!(get_staged_diff(repo.path(), true)
+        .unwrap()
+        .contains("+++ b/a.txt"));
+    commit(repo.path(), "feat: add the a file", true).unwrap();
+    assert_eq!(
+        git(repo.path(), &["log", "-2", "--format=%s"]).trim(),
+        "feat: add the a file\nInitial commit"
+    );
+}
+
+#[test]
+fn stash_guard_restores_changes_when_dropped() {
+    let repo = TestRepo::new();
+    std::fs::write(repo.path().join("README.md"), "# edited\n").unwrap();
+    std::fs::write(repo.path().join("notes.txt"), "wip\n").unwrap();
+
+    let _lock = STASHES.lock().unwrap();
+    let stash = stash_changes(repo.path()).unwrap();
+    assert!(stash.is_some());
+    assert!(check_uncommitted_changes(repo.path()).is_ok());
+    drop(stash);
+
+    assert_eq!(
+        std::fs::read_to_string(repo.path().join("README.md")).unwrap(),
+        "# edited\n"
+    );
+    assert!(repo.path().join("notes.txt").exists());
+    assert!(stash_changes(TestRepo::new().path()).unwrap().is_none());
+}
+
+#[test]
+fn stashed_changes_can_be_restored_without_dropping_the_guard() {
+    let repo = TestRepo::new();
+    std::fs::write(repo.path().join("README.md"), "# edited\n").unwrap();
+
+    let _lock = STASHES.lock().unwrap();
+    let stash = stash_changes(repo.path()).unwrap();
+    restore_stashes();
+    assert_eq!(
+        std::fs::read_to_string(repo.path().join("README.md")).unwrap(),
+        "# edited\n"
+    );
+
+    // The guard doesn't pop a second time
+    drop(stash);
+    assert!(git(repo.path(), &["stash", "list"]).is_empty());
+    assert_eq!(
+        std::fs::read_to_string(repo.path().join("README.md")).unwrap(),
+        "# edited\n"
+    );
+}
+
+#[test]
+fn latest_tag_is_the_most_recent_reachable_tag() {
+    let repo = TestRepo::new();
+    assert!(latest_tag(repo.path()).is_err());
+
+    git(repo.path(), &["tag", "v0.1.0"]);
+    repo.commit("a.txt", "a\n", "Add a");
+    git(repo.path(), &["tag", "v0.2.0"]);
+    repo.commit("b.txt", "b\n", "Add b");
+
+    assert_eq!(latest_tag(repo.path()).unwrap(), "v0.2.0");
+}
+
+#[test]
+fn commit_authors_include_co_authors_once() {
+    let repo = TestRepo::new();
+    repo.commit(
+        "a.txt",
+        "a\n",
+        "Add a\n\nCo-authored-by: Pat Pair <pat@example.com>",
+    );
+    repo.commit(
+        "b.txt",
+        "b\n",
+        "Add b\n\nCo-authored-by: Pat Pair <pat@example.com>",
+    );
+
+    let authors = get_commit_authors(repo.path(), &base_range("origin", "master"), false).unwrap();
+
+    let author = git(repo.path(), &["log", "-1", "--format=%an"]);
+    assert_eq!(authors, [author.trim(), "Pat Pair"]);
+}
+
+#[test]
+fn work_in_progress_is_read_from_branch_words_and_the_latest_subject() {
+    let patterns = DEFAULT_WIP_PATTERNS.map(String::from);
+
+    assert!(is_work_in_progress("wip/login", None, &patterns));
+    assert!(is_work_in_progress("feature/login-DRAFT", None, &patterns));
+    assert!(is_work_in_progress(
+        "login",
+        Some("WIP: login form"),
+        &patterns
+    ));
+    assert!(is_work_in_progress(
+        "login",
+        Some("[wip] login form"),
+        &patterns
+    ));
+    assert!(!is_work_in_progress("swipe-gestures", None, &patterns));
+    assert!(!is_work_in_progress(
+        "login",
+        Some("Fix the wip marker"),
+        &patterns
+    ));
+    assert!(!is_work_in_progress("wip/login", None, &[]));
+}
+
+#[test]
+fn scopes_are_read_from_conventional_subjects() {
+    assert!(has_scope("feat(api): add pagination", "api"));
+    assert!(has_scope("fix(api)!: drop the v1 routes", "api"));
+    assert!(!has_scope("feat(cli): add --scope", "api"));
+    assert!(!has_scope("feat: no scope at all", "api"));
+    assert!(!has_scope("Update the api docs", "api"));
+}
+
+#[test]
+fn git_revert_commits_are_detected() {
+    let repo = TestRepo::new();
+    repo.commit("cache.txt", "sessions\n", "Cache sessions");
+    let original = get_head_sha(repo.path()).unwrap();
+    git(repo.path(), &["revert", "--no-edit", "HEAD"]);
+
+    let reverts = get_reverts(repo.path(), "HEAD~1..HEAD", false).unwrap();
+
+    assert_eq!(reverts.len(), 1);
+    assert_eq!(reverts[0].subject, "Cache sessions");
+    assert_eq!(reverts[0].sha.as_deref(), Some(original.as_str()));
+}
+
+#[test]
+fn hand_made_inverse_of_a_base_commit_is_detected() {
+    let repo = TestRepo::new();
+    git(repo.path(), &["checkout", "-q", "master"]);
+    repo.commit("cache.txt", "sessions\n", "Cache sessions (#41)");
+    let original = get_head_sha(repo.path()).unwrap();
+    git(repo.path(), &["push", "-q", "origin", "master"]);
+    git(repo.path(), &["checkout", "-q", "-B", "feature"]);
+    git(repo.path(), &["rm", "-q", "cache.txt"]);
+    git(
+        repo.path(),
+        &["commit", "-q", "-m", "Back out the session cache"],
+    );
+
+    let revert =
+        find_inverted_commit(repo.path(), "origin/master..feature", "origin/master").unwrap();
+
+    let revert = revert.expect("the inverse is found");
+    assert_eq!(revert.subject, "Cache sessions (#41)");
+    assert_eq!(revert.sha, Some(original));
+
+    repo.commit("other.txt", "more\n", "Unrelated change");
+    assert_eq!(
+        find_inverted_commit(repo.path(), "origin/master..feature", "origin/master").unwrap(),
+        None
+    );
+}
+
+#[test]
+fn inverse_search_fails_when_the_base_is_missing() {
+    let repo = TestRepo::new();
+    repo.commit("a.txt", "a\n", "Add a");
+
+    assert!(find_inverted_commit(repo.path(), "origin/master..feature", "origin/gone").is_err());
+}
+
+#[test]
+fn commits_are_grouped_by_author_without_merges() {
+    let repo = TestRepo::new();
+    repo.commit("a.txt", "a\n", "Add a");
+    git(repo.path(), &["checkout", "-q", "-b", "pair"]);
+    std::fs::write(repo.path().join("b.txt"), "b\n").unwrap();
+    git(repo.path(), &["add", "b.txt"]);
+    git(
+        repo.path(),
+        &[
+            "commit",
+            "-q",
+            "--author",
+            "Pat Pair <pat@example.com>",
+            "-m",
+            "Add b",
+        ],
+    );
+    git(repo.path(), &["checkout", "-q", "feature"]);
+    repo.commit("c.txt", "c\n", "Add c");
+    git(
+        repo.path(),
+        &["merge", "-q", "--no-ff", "-m", "Merge pair", "pair"],
+    );
+
+    let groups =
+        get_commits_by_author(repo.path(), &base_range("origin", "master"), false).unwrap();
+
+    let subjects = |subjects: &[&str]| subjects.iter().map(|s| s.to_string()).collect();
+    assert_eq!(
+        groups,
+        [
+            AuthorCommits {
+                author: "Test User".to_string(),
+                subjects: subjects(&["Add a", "Add c"]),
+            },
+            AuthorCommits {
+                author: "Pat Pair".to_string(),
+                subjects: subjects(&["Add b"]),
+            },
+        ]
+    );
+}
+
+#[test]
+fn moved_files_are_diffed_as_renames() {
+    let repo = TestRepo::new();
+    let contents: String = (1..=20).map(|n| format!("line {}\n", n)).collect();
+    repo.commit("old.txt", &contents, "Add old");
+    git(repo.path(), &["mv", "old.txt", "new.txt"]);
+    std::fs::write(
+        repo.path().join("new.txt"),
+        contents.replace("line 20", "line twenty"),
+    )
+    .unwrap();
+    git(repo.path(), &["commit", "-qam", "Move old"]);
+
+    let diff = get_git_diff(
+        repo.path(),
+        "HEAD~1..HEAD",
+        0,
+        None,
+        DEFAULT_RENAME_THRESHOLD,
+    )
+    .unwrap();
+    assert!(
+        diff.contains("rename from old.txt\nrename to new.txt"),
+        "{}",
+        diff
+    );
+
+    let diff = get_git_diff(repo.path(), "HEAD~1..HEAD", 0, None, 100).unwrap();
+    assert!(diff.contains("deleted file mode"), "{}", diff);
+}
@@ -0,0 +1,111 @@
+use pullrequest::issue::{detect_issues, issue_links_block, IssueRef, JiraConfig};
+
+fn commits(messages: &[&str]) -> Vec<String> {
+    messages.iter().map(|m| m.to_string()).collect()
+}
+
+#[test]
+fn github_issues_come_from_branch_and_commits() {
+    assert_eq!(
+        detect_issues(
+            "123-fix-login",
+            &commits(&["Fixes #45 too", "Bump SHA-256 impl"])
+        ),
+        vec![IssueRef::GitHub(123), IssueRef::GitHub(45)]
+    );
+    assert_eq!(
+        detect_issues("feature/issue-9-docs", &[]),
+        vec![IssueRef::GitHub(9)]
+    );
+    assert_eq!(detect_issues("issues/77", &[]), vec![IssueRef::GitHub(77)]);
+}
+
+#[test]
+fn only_references_after_a_closing_keyword_are_closed() {
+    let issues = detect_issues(
+        "feature/login",
+        &commits(&[
+            "Fix the login form (#45)",
+            "Revert \"Add sessions (#44)\"",
+            "Handle #46 too",
+            "resolves: #47",
+        ]),
+    );
+
+    assert_eq!(
+        issues,
+        vec![
+            IssueRef::GitHubMention(45),
+            IssueRef::GitHubMention(44),
+            IssueRef::GitHubMention(46),
+            IssueRef::GitHub(47),
+        ]
+    );
+}
+
+#[test]
+fn a_closed_reference_is_not_also_a_mention() {
+    assert_eq!(
+        detect_issues("45-login", &commits(&["Tidy up (#45)"])),
+        vec![IssueRef::GitHub(45)]
+    );
+    assert_eq!(
+        detect_issues("login", &commits(&["Start on #45", "Closes #45"])),
+        vec![IssueRef::GitHub(45)]
+    );
+}
+
+#[test]
+fn version_like_branch_segments_are_not_issues() {
+    assert!(detect_issues("feature/2fa-setup", &[]).is_empty());
+    assert!(detect_issues("release-notes", &commits(&["Fix C#1 parser"])).is_empty());
+}
+
+#[test]
+fn jira_keys_come_from_branch_and_subject_prefixes() {
+    assert_eq!(
+        detect_issues(
+            "PROJ-1234-feature",
+            &commits(&[
+                "[OPS-9] Tweak deploy",
+                "Use UTF-8 everywhere",
+                "PROJ-1234: more"
+            ])
+        ),
+        vec![
+            IssueRef::Jira("PROJ-1234".to_string()),
+            IssueRef::Jira("OPS-9".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn links_block_closes_github_issues_and_links_jira() {
+    let jira = JiraConfig {
+        base_url: "https://acme.atlassian.net/".to_string(),
+        token: None,
+        email: None,
+    };
+    let issues = [IssueRef::GitHub(12), IssueRef::Jira("PROJ-1".to_string())];
+    let summaries = [("PROJ-1".to_string(), "Login is slow".to_string())];
+
+    assert_eq!(
+        issue_links_block(&issues, Some(&jira), &summaries),
+        "\n\nCloses #12\nJira: [PROJ-1](https://acme.atlassian.net/browse/PROJ-1): Login is slow"
+    );
+    assert_eq!(issue_links_block(&issues, None, &[]), "\n\nCloses #12");
+    assert_eq!(issue_links_block(&[], None, &[]), "");
+}
+
+#[test]
+fn links_block_refers_to_mentioned_numbers_without_closing_them() {
+    let issues = detect_issues(
+        "feature/login",
+        &commits(&["Fix the login form (#45)", "Fixes #47"]),
+    );
+
+    let block = issue_links_block(&issues, None, &[]);
+
+    assert_eq!(block, "\n\nRefs #45\nCloses #47");
+    assert!(!block.contains("Closes #45"));
+}
@@ -0,0 +1,20 @@
+use pullrequest::issue::IssueRef;
+use pullrequest::plan::Plan;
+
+#[test]
+fn plan_lists_every_resolved_setting() {
+    let plan = Plan {
+        base: "main".to_string(),
+        head: "fix-login".to_string(),
+        title: "Fix login".to_string(),
+        reviewers: vec!["ana".to_string(), "bo".to_string()],
+        issues: vec![IssueRef::GitHub(42)],
+        ..Default::default()
+    };
+
+    assert_eq!(
+        plan.render(),
+        "Plan:\n  Base:      main\n  Head:      fix-login\n  Title:     Fix login\n  \
+         Reviewers: ana, bo\n  Projects:  none\n  Issues:    #42\n  Draft:     no"
+    );
+}
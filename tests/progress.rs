@@ -0,0 +1,8 @@
+use pullrequest::progress::format_count;
+
+#[test]
+fn counts_are_grouped_in_thousands() {
+    assert_eq!(format_count(7), "7");
+    assert_eq!(format_count(3_402), "3,402");
+    assert_eq!(format_count(1_000_000), "1,000,000");
+}
@@ -0,0 +1,36 @@
+use pullrequest::resume::{diff_hash, RunState};
+use std::path::Path;
+
+fn state(diff: &str) -> RunState {
+    RunState {
+        diff_hash: diff_hash(diff),
+        base: "master".to_string(),
+        branch: "feature/login".to_string(),
+        title: "Add login".to_string(),
+        description: "Adds a login page.".to_string(),
+    }
+}
+
+#[test]
+fn run_state_is_saved_per_repository_and_branch() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = RunState::path(dir.path(), Path::new("/src/app"), "feature/login");
+    let other = RunState::path(dir.path(), Path::new("/src/app"), "feature-login");
+    assert_ne!(path, other);
+    assert!(RunState::load(&path).unwrap().is_none());
+
+    state("+login").save(&path).unwrap();
+
+    assert_eq!(RunState::load(&path).unwrap(), Some(state("+login")));
+    assert!(RunState::load(&other).unwrap().is_none());
+}
+
+#[test]
+fn run_state_is_stale_once_the_branch_diff_or_base_changes() {
+    let saved = state("+login");
+
+    assert!(saved.is_current("feature/login", &diff_hash("+login"), "master"));
+    assert!(!saved.is_current("feature-login", &diff_hash("+login"), "master"));
+    assert!(!saved.is_current("feature/login", &diff_hash("+login\n+logout"), "master"));
+    assert!(!saved.is_current("feature/login", &diff_hash("+login"), "develop"));
+}
@@ -0,0 +1,114 @@
+use std::time::Duration;
+
+use pullrequest::retry::{retry, retry_async, Backoff, Retry};
+
+const BACKOFF: Backoff = Backoff {
+    attempts: 4,
+    initial: Duration::from_secs(1),
+    max: Duration::from_secs(3),
+    jitter: false,
+};
+
+#[test]
+fn delay_doubles_up_to_the_max() {
+    let delays: Vec<_> = (1..=4).map(|attempt| BACKOFF.delay(attempt)).collect();
+
+    assert_eq!(delays, [1, 2, 3, 3].map(Duration::from_secs));
+}
+
+#[test]
+fn jitter_stays_between_half_and_the_full_delay() {
+    let backoff = Backoff {
+        jitter: true,
+        ..BACKOFF
+    };
+
+    for _ in 0..100 {
+        let delay = backoff.delay(2);
+        assert!(
+            (Duration::from_secs(1)..=Duration::from_secs(2)).contains(&delay),
+            "{:?}",
+            delay
+        );
+    }
+}
+
+#[test]
+fn gives_up_after_the_last_attempt() {
+    let backoff = Backoff {
+        initial: Duration::ZERO,
+        ..BACKOFF
+    };
+    let mut asked = Vec::new();
+
+    let result: Result<(), u32> = retry(
+        &backoff,
+        |_, attempt| {
+            asked.push(attempt);
+            true
+        },
+        Err,
+    );
+
+    assert_eq!(result, Err(4));
+    assert_eq!(asked, [1, 2, 3, 4]);
+}
+
+#[test]
+fn stops_at_an_error_that_is_not_retryable() {
+    let backoff = Backoff {
+        initial: Duration::ZERO,
+        ..BACKOFF
+    };
+
+    let result: Result<(), &str> = retry(
+        &backoff,
+        |error, _| *error == "transient",
+        |attempt| Err(if attempt == 1 { "transient" } else { "fatal" }),
+    );
+
+    assert_eq!(result, Err("fatal"));
+}
+
+#[tokio::test(start_paused = true)]
+async fn async_retry_waits_the_backoff_between_attempts() {
+    let started = tokio::time::Instant::now();
+
+    let result = retry_async(
+        &BACKOFF,
+        |_: &(), _| true,
+        |attempt| async move {
+            if attempt < 3 {
+                Err(())
+            } else {
+                Ok(attempt)
+            }
+        },
+    )
+    .await;
+
+    assert_eq!(result, Ok(3));
+    // 1s after the first failure, 2s after the second
+    assert_eq!(started.elapsed().as_secs(), 3);
+}
+
+#[tokio::test(start_paused = true)]
+async fn requested_wait_replaces_the_backoff() {
+    let started = tokio::time::Instant::now();
+
+    let result = retry_async(
+        &BACKOFF,
+        |_: &(), _| Retry::After(Duration::from_secs(30)),
+        |attempt| async move {
+            if attempt < 2 {
+                Err(())
+            } else {
+                Ok(())
+            }
+        },
+    )
+    .await;
+
+    assert_eq!(result, Ok(()));
+    assert_eq!(started.elapsed().as_secs(), 30);
+}
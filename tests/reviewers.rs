@@ -0,0 +1,58 @@
+use pullrequest::reviewers::{check_min_reviewers, without_excluded, Rotation};
+
+fn team() -> Vec<String> {
+    ["ana", "bo", "cy", "di"].map(String::from).to_vec()
+}
+
+#[test]
+fn picks_continue_around_the_team() {
+    let mut rotation = Rotation::default();
+
+    assert_eq!(rotation.pick("repo", &team(), &[], 1), ["ana"]);
+    assert_eq!(rotation.pick("repo", &team(), &[], 2), ["bo", "cy"]);
+    assert_eq!(rotation.pick("repo", &team(), &[], 2), ["di", "ana"]);
+}
+
+#[test]
+fn skipped_members_are_passed_over() {
+    let mut rotation = Rotation::default();
+    let skip = ["bo".to_string(), "cy".to_string()];
+
+    assert_eq!(rotation.pick("repo", &team(), &skip, 1), ["ana"]);
+    assert_eq!(rotation.pick("repo", &team(), &skip, 1), ["di"]);
+    assert_eq!(rotation.pick("repo", &team(), &skip, 5), ["ana", "di"]);
+}
+
+#[test]
+fn rotation_is_saved_per_repository() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("state").join("rotation.json");
+    let mut rotation = Rotation::load(&path).unwrap();
+    rotation.pick("one", &team(), &[], 1);
+    rotation.save(&path).unwrap();
+
+    let mut rotation = Rotation::load(&path).unwrap();
+
+    assert_eq!(rotation.pick("one", &team(), &[], 1), ["bo"]);
+    assert_eq!(rotation.pick("two", &team(), &[], 1), ["ana"]);
+}
+
+#[test]
+fn excluded_logins_are_dropped_case_insensitively() {
+    let reviewers = ["ana", "Dependabot[bot]", "bo"].map(String::from);
+    let excluded = ["dependabot[bot]", "@BO"].map(String::from);
+
+    assert_eq!(without_excluded(&reviewers, &excluded), ["ana"]);
+}
+
+#[test]
+fn too_few_reviewers_are_listed_in_the_error() {
+    assert!(check_min_reviewers(&team(), 2).is_ok());
+
+    let error = check_min_reviewers(&team()[..1], 2).unwrap_err();
+    assert!(error.contains("At least 2 reviewers"), "{}", error);
+    assert!(error.contains("only 1 resolved: ana"), "{}", error);
+
+    let error = check_min_reviewers(&[], 1).unwrap_err();
+    assert!(error.contains("none were resolved"), "{}", error);
+}
@@ -0,0 +1,72 @@
+use pullrequest::template::fetch_template;
+use wiremock::matchers::{header, method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+#[tokio::test]
+async fn template_is_cached_and_revalidated_with_its_etag() {
+    let server = MockServer::start().await;
+    let cache = tempfile::tempdir().unwrap();
+    let url = format!("{}/template.md", server.uri());
+
+    Mock::given(method("GET"))
+        .and(path("/template.md"))
+        .and(header("if-none-match", "\"v1\""))
+        .respond_with(ResponseTemplate::new(304))
+        .expect(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/template.md"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("etag", "\"v1\"")
+                .set_body_string("Use the team format."),
+        )
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let first = fetch_template(&url, cache.path()).await.unwrap();
+    let second = fetch_template(&url, cache.path()).await.unwrap();
+
+    assert_eq!(first, "Use the team format.");
+    assert_eq!(second, "Use the team format.");
+}
+
+#[tokio::test]
+async fn cached_template_is_used_when_the_server_fails() {
+    let server = MockServer::start().await;
+    let cache = tempfile::tempdir().unwrap();
+    let url = format!("{}/template.md", server.uri());
+
+    Mock::given(method("GET"))
+        .respond_with(ResponseTemplate::new(200).set_body_string("Use the team format."))
+        .up_to_n_times(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .respond_with(ResponseTemplate::new(503))
+        .mount(&server)
+        .await;
+
+    fetch_template(&url, cache.path()).await.unwrap();
+
+    assert_eq!(
+        fetch_template(&url, cache.path()).await.unwrap(),
+        "Use the team format."
+    );
+}
+
+#[tokio::test]
+async fn unreachable_template_without_a_cache_is_an_error() {
+    let server = MockServer::start().await;
+    let cache = tempfile::tempdir().unwrap();
+    Mock::given(method("GET"))
+        .respond_with(ResponseTemplate::new(404))
+        .mount(&server)
+        .await;
+
+    let result = fetch_template(&format!("{}/template.md", server.uri()), cache.path()).await;
+
+    assert!(result.is_err());
+}